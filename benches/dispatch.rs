@@ -0,0 +1,66 @@
+//! Per-message dispatch cost on a dense controller: 96 CC components, 32 of
+//! them 14-bit, 16 pads, nine banks, every component bound in every bank.
+//!
+//! Bindings on every bank are kept once and shared by all nine banks, as are
+//! the routing tables, so this setup holds a single copy of each component.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use midly::num::{u4, u7};
+
+use midlman::midi::{Address, Banks, Controller, Preset, Value};
+
+const PRESET: &str = r#"
+banks: 9
+components:
+  sliders: "0-31,64-95"
+  buttons: "96-119"
+  dials: "120-127"
+  pads: "36-51"
+  high_resolution: "0-31"
+"#;
+
+fn controller() -> Controller {
+    let preset: Preset = serde_yaml::from_str(PRESET).unwrap();
+    let mut controller = Controller::from_preset(preset).unwrap();
+    let ccs = (0..32).chain(64..128).map(|cc| Address::Cc(u7::from(cc)));
+    let notes = (36..52).map(|note| Address::Note(u7::from(note)));
+    for address in ccs.chain(notes) {
+        controller
+            .bind(
+                Banks::All,
+                0,
+                None,
+                address,
+                Box::new(|value: Value| {
+                    black_box(value);
+                }),
+            )
+            .unwrap();
+    }
+    controller
+}
+
+fn dispatch(c: &mut Criterion) {
+    let channel = u4::from(0);
+    let mut controller = controller();
+    c.bench_function("7-bit CC", |b| {
+        b.iter(|| controller.set_component(channel, black_box(u7::from(70)), u7::from(64), 0))
+    });
+    c.bench_function("14-bit CC pair", |b| {
+        b.iter(|| {
+            controller.set_component(channel, black_box(u7::from(5)), u7::from(64), 0);
+            controller.set_component(channel, black_box(u7::from(37)), u7::from(1), 0);
+        })
+    });
+    c.bench_function("note", |b| {
+        b.iter(|| controller.set_note(channel, black_box(u7::from(40)), u7::from(100)))
+    });
+    c.bench_function("unassigned CC", |b| {
+        b.iter(|| controller.set_component(channel, black_box(u7::from(60)), u7::from(64), 0))
+    });
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,486 @@
+use std::sync::mpsc::Sender;
+
+use midly::num::u7;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::midi::{
+    Banks, ButtonEvent, ComponentCallback, ComponentRef, Controller, Feedback, FeedbackCallback,
+    FeedbackState, SharedSink, Value, ValueSource,
+};
+use crate::volume::{AudioError, SharedBackend, Target};
+use crate::worker::{AudioEvent, AudioWorker, Change, Volumes};
+
+/// What a bound component does to its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Sets the volume from the component value, or steps it for an endless
+    /// encoder.
+    Volume,
+    /// Toggles mute when the component is pressed (any non-zero value). Bound
+    /// to a button's `toggle` event, mutes while the button is latched on.
+    Mute,
+}
+
+/// Binds one component to an action on a target.
+#[derive(Debug, Deserialize)]
+pub struct Binding {
+    /// The bank (0-127) the binding applies to, or `all` for every bank.
+    #[serde(deserialize_with = "deserialize_banks")]
+    pub bank: Banks,
+    /// The layer within the bank, 0 for the base layer or `n` for while
+    /// modifier `n` is held.
+    #[serde(default)]
+    pub layer: u8,
+    #[serde(flatten)]
+    pub component: ComponentRef,
+    /// The button event that triggers the action, `press` by default.
+    #[serde(default)]
+    pub on: ButtonEvent,
+    pub action: Action,
+    /// How far one step of an endless encoder moves the volume.
+    #[serde(default = "default_step")]
+    pub step: f32,
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub target: Target,
+}
+
+fn deserialize_banks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Banks, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Bank {
+        Number(u8),
+        Name(String),
+    }
+    match Bank::deserialize(deserializer)? {
+        Bank::Number(bank) => u7::try_from(bank)
+            .map(Banks::One)
+            .ok_or_else(|| D::Error::custom(format!("bank {} is above 127", bank))),
+        Bank::Name(name) if name == "all" => Ok(Banks::All),
+        Bank::Name(name) => Err(D::Error::custom(format!(
+            "unknown bank \"{}\", expected a number or \"all\"",
+            name
+        ))),
+    }
+}
+
+/// One encoder step moves the volume by 2%.
+fn default_step() -> f32 {
+    0.02
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Bindings {
+    pub bindings: Vec<Binding>,
+}
+
+impl Bindings {
+    /// Binds every binding to its components on `controller`, queueing their
+    /// actions on `worker`. Bindings to components or banks the controller
+    /// doesn't have are reported and skipped.
+    pub fn bind(&self, controller: &mut Controller, backend: &SharedBackend, worker: &AudioWorker) {
+        for binding in &self.bindings {
+            let resolved = binding
+                .component
+                .channel()
+                .and_then(|channel| Ok((channel, binding.component.address()?)));
+            let (channel, address) = match resolved {
+                Ok(resolved) => resolved,
+                Err(err) => {
+                    eprintln!("ERROR: Binding {err}");
+                    continue;
+                }
+            };
+            let (banks, layer) = (binding.bank, binding.layer);
+            let feedback = controller
+                .feedback(channel, address)
+                .zip(controller.sink().cloned())
+                .map(|(feedback, sink)| binding.feedback(backend.clone(), feedback, sink));
+            let callback = binding.callback(worker.events(), feedback.clone());
+            let bound = controller
+                .bind_event(banks, layer, channel, address, binding.on, callback)
+                .and_then(|()| match feedback {
+                    Some(feedback) => {
+                        let sync = binding.sync(worker.events(), feedback);
+                        controller.bind_feedback(banks, layer, channel, address, sync)
+                    }
+                    None => Ok(()),
+                })
+                .and_then(
+                    |()| match binding.source(worker.events(), worker.volumes()) {
+                        Some(source) => {
+                            controller.bind_source(banks, layer, channel, address, source)
+                        }
+                        None => Ok(()),
+                    },
+                );
+            if let Err(err) = bound {
+                eprintln!("ERROR: Binding {err}");
+                continue;
+            }
+            println!(
+                "Bound {}{} {}{}{} to {:?} {}",
+                binding.bank,
+                match binding.layer {
+                    0 => String::new(),
+                    layer => format!(" layer {}", layer),
+                },
+                address,
+                channel
+                    .map(|c| format!(" on channel {}", c.as_int() + 1))
+                    .unwrap_or_default(),
+                match binding.on {
+                    ButtonEvent::Press => String::new(),
+                    event => format!(" ({:?})", event),
+                },
+                binding.action,
+                binding.target
+            );
+        }
+    }
+}
+
+impl Binding {
+    /// The callback that queues the action on the [`AudioWorker`].
+    /// `feedback`, if any, runs after the action is applied to show the
+    /// target's new state on the controller.
+    ///
+    /// [`AudioWorker`]: crate::worker::AudioWorker
+    pub fn callback(
+        &self,
+        events: Sender<AudioEvent>,
+        feedback: Option<Box<dyn FeedbackCallback>>,
+    ) -> Box<dyn ComponentCallback> {
+        let target = self.target.clone();
+        let step = self.step;
+        let on = self.on;
+        let action = self.action;
+        Box::new(move |value: Value| {
+            let change = match (action, value) {
+                (Action::Volume, Value::Relative(steps)) => Change::StepVolume(steps as f32 * step),
+                (Action::Volume, _) => Change::SetVolume(value.normalized()),
+                (Action::Mute, _) if on == ButtonEvent::Toggle => {
+                    Change::SetMute(value.as_int() > 0)
+                }
+                // releases come as 0, and still toggle when bound to `release`
+                (Action::Mute, _) if value.as_int() > 0 || on == ButtonEvent::Release => {
+                    Change::ToggleMute
+                }
+                (Action::Mute, _) => return,
+            };
+            let event = AudioEvent::new(target.clone(), change).with_feedback(feedback.clone());
+            if events.send(event).is_err() {
+                eprintln!("ERROR: The audio worker has stopped");
+            }
+        })
+    }
+    /// The callback that queues `show` to run on the [`AudioWorker`], for
+    /// [`Controller::sync`].
+    ///
+    /// [`AudioWorker`]: crate::worker::AudioWorker
+    /// [`Controller::sync`]: crate::midi::Controller::sync
+    pub fn sync(
+        &self,
+        events: Sender<AudioEvent>,
+        show: Box<dyn FeedbackCallback>,
+    ) -> Box<dyn FeedbackCallback> {
+        let event = AudioEvent::new(self.target.clone(), Change::Refresh).with_feedback(Some(show));
+        Box::new(move || {
+            let _ = events.send(event.clone());
+        })
+    }
+    /// The callback that sends the target's current state through
+    /// `feedback`: the volume level, or the mute state as on/off. A target
+    /// that isn't running shows as off.
+    pub fn feedback(
+        &self,
+        backend: SharedBackend,
+        feedback: Feedback,
+        sink: SharedSink,
+    ) -> Box<dyn FeedbackCallback> {
+        let target = self.target.clone();
+        let action = self.action;
+        Box::new(move || {
+            let state = {
+                let backend = backend.lock().unwrap();
+                match action {
+                    Action::Volume => backend.get_volume(&target).map(FeedbackState::Level),
+                    Action::Mute => backend.get_mute(&target).map(FeedbackState::Switch),
+                }
+            };
+            match state {
+                Ok(state) => feedback.send(&sink, state),
+                Err(AudioError::NotFound(_)) => feedback.send(&sink, FeedbackState::Switch(false)),
+                Err(err) => eprintln!("ERROR: {err}"),
+            }
+        })
+    }
+    /// Reads the target's volume, for soft takeover. Only volume bindings
+    /// have a value to take over.
+    pub fn source(
+        &self,
+        events: Sender<AudioEvent>,
+        volumes: Volumes,
+    ) -> Option<Box<dyn ValueSource>> {
+        match self.action {
+            Action::Volume => Some(Box::new(VolumeSource {
+                target: self.target.clone(),
+                events,
+                volumes,
+            })),
+            Action::Mute => None,
+        }
+    }
+}
+
+/// A target's volume as the worker last applied or read it. Components
+/// refresh it when bound and on every bank or layer change, so the volume is
+/// known before they are first moved, and the worker keeps it up to date as
+/// it applies their changes.
+#[derive(Clone)]
+struct VolumeSource {
+    target: Target,
+    events: Sender<AudioEvent>,
+    volumes: Volumes,
+}
+
+impl ValueSource for VolumeSource {
+    fn value(&self) -> Option<f32> {
+        self.volumes.lock().unwrap().get(&self.target).copied()
+    }
+    fn refresh(&self) {
+        let _ = self
+            .events
+            .send(AudioEvent::new(self.target.clone(), Change::Refresh));
+    }
+    fn clone_box<'a>(&self) -> Box<dyn 'a + ValueSource>
+    where
+        Self: 'a,
+    {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::midi::{fixtures, MidiSink};
+    use crate::volume::MockAudioBackend;
+
+    const PRESET: &str = r#"
+components:
+  encoders:
+    binary_offset: "12"
+  takeover:
+    pickup: "11"
+feedback:
+  - { cc: 26, send: { note: 26 } }
+"#;
+
+    type Sent = Arc<Mutex<Vec<Vec<u8>>>>;
+
+    struct Recorder(Sent);
+
+    impl MidiSink for Recorder {
+        fn send(&mut self, message: &[u8]) -> Result<(), String> {
+            self.0.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    struct Rig {
+        controller: Controller,
+        backend: SharedBackend,
+        worker: AudioWorker,
+        sent: Sent,
+    }
+
+    impl Rig {
+        fn new(backend: MockAudioBackend, bindings: &str) -> Self {
+            let mut controller = fixtures::controller(PRESET);
+            let sent = Sent::default();
+            controller.set_sink(Arc::new(Mutex::new(Box::new(Recorder(sent.clone())))));
+            let backend: SharedBackend = Arc::new(Mutex::new(Box::new(backend)));
+            let worker = AudioWorker::spawn(backend.clone(), false);
+            let bindings: Bindings = serde_yaml::from_str(bindings).unwrap();
+            bindings.bind(&mut controller, &backend, &worker);
+            Self {
+                controller,
+                backend,
+                worker,
+                sent,
+            }
+        }
+        /// Sends `message` and waits for the worker to apply what it queued.
+        fn send(&mut self, message: &[u8]) {
+            self.controller.handle_message(0, message, false).unwrap();
+            self.flush();
+        }
+        fn flush(&self) {
+            let (done, wait) = mpsc::channel();
+            let show = move || {
+                let _ = done.send(());
+            };
+            let event = AudioEvent::new(Target::Pid(u32::MAX), Change::Refresh)
+                .with_feedback(Some(Box::new(show)));
+            self.worker.events().send(event).unwrap();
+            wait.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        fn volume(&self, target: &Target) -> f32 {
+            self.backend.lock().unwrap().get_volume(target).unwrap()
+        }
+        fn mute(&self, target: &Target) -> bool {
+            self.backend.lock().unwrap().get_mute(target).unwrap()
+        }
+    }
+
+    fn spotify() -> Target {
+        Target::Process("Spotify".to_string())
+    }
+
+    fn backend() -> MockAudioBackend {
+        MockAudioBackend::new()
+            .with_device("master", 1.0, false)
+            .with_session("Spotify", 1000, 0.5, false)
+    }
+
+    #[test]
+    fn volume_follows_the_slider() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings: [{ bank: 0, cc: 5, action: volume, target: { process: Spotify } }]",
+        );
+        rig.send(&[0xb0, 5, 127]);
+        assert_eq!(rig.volume(&spotify()), 1.0);
+        rig.send(&[0xb0, 5, 0]);
+        assert_eq!(rig.volume(&spotify()), 0.0);
+        assert_eq!(rig.volume(&Target::Master), 1.0);
+    }
+
+    #[test]
+    fn mute_toggles_on_press() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings: [{ bank: 0, cc: 25, action: mute, target: { process: Spotify } }]",
+        );
+        rig.send(&[0xb0, 25, 127]);
+        assert!(rig.mute(&spotify()));
+        rig.send(&[0xb0, 25, 0]);
+        assert!(rig.mute(&spotify()));
+        rig.send(&[0xb0, 25, 127]);
+        assert!(!rig.mute(&spotify()));
+    }
+
+    #[test]
+    fn encoder_steps_the_volume() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings: [{ bank: 0, cc: 12, action: volume, step: 0.1, target: { process: Spotify } }]",
+        );
+        // binary offset, 62 is two steps down
+        rig.send(&[0xb0, 12, 62]);
+        assert!((rig.volume(&spotify()) - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn encoder_steps_up_from_the_volume_read_back() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings: [{ bank: 0, cc: 12, action: volume, step: 0.1, target: { process: Spotify } }]",
+        );
+        let mut last = rig.volume(&spotify());
+        for _ in 0..3 {
+            rig.send(&[0xb0, 12, 65]);
+            let volume = rig.volume(&spotify());
+            assert!(volume > last, "{volume} is not above {last}");
+            last = volume;
+        }
+        assert!((last - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn backend_failures_leave_other_targets_working() {
+        let mut failing = backend();
+        failing.fail(spotify(), AudioError::Backend("device lost".to_string()));
+        let mut rig = Rig::new(
+            failing,
+            "bindings:
+              - { bank: 0, cc: 5, action: volume, target: { process: Spotify } }
+              - { bank: 0, cc: 6, action: volume, target: master }",
+        );
+        rig.send(&[0xb0, 5, 0]);
+        rig.send(&[0xb0, 6, 0]);
+        assert_eq!(rig.volume(&Target::Pid(1000)), 0.5);
+        assert_eq!(rig.volume(&Target::Master), 0.0);
+    }
+
+    #[test]
+    fn missing_targets_show_as_off() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings: [{ bank: 0, cc: 26, action: mute, target: { process: Discord } }]",
+        );
+        rig.send(&[0xb0, 26, 127]);
+        rig.controller.sync();
+        rig.flush();
+        assert_eq!(*rig.sent.lock().unwrap(), vec![vec![0x90, 26, 0]]);
+    }
+
+    #[test]
+    fn bindings_only_apply_to_their_bank() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings:
+              - { bank: 1, cc: 5, action: volume, target: { process: Spotify } }
+              - { bank: 20, cc: 6, action: volume, target: master }",
+        );
+        rig.send(&[0xb0, 5, 0]);
+        rig.send(&[0xb0, 6, 0]);
+        assert_eq!(rig.volume(&spotify()), 0.5);
+        assert_eq!(rig.volume(&Target::Master), 1.0);
+        rig.controller.set_bank(u7::from(1)).unwrap();
+        rig.send(&[0xb0, 5, 0]);
+        assert_eq!(rig.volume(&spotify()), 0.0);
+    }
+
+    #[test]
+    fn takeover_knows_the_volume_before_the_first_move() {
+        let mut rig = Rig::new(
+            backend(),
+            "bindings:
+              - { bank: all, cc: 11, action: volume, target: { process: Spotify } }",
+        );
+        rig.flush();
+        let cached = |rig: &Rig| {
+            rig.worker
+                .volumes()
+                .lock()
+                .unwrap()
+                .get(&spotify())
+                .copied()
+        };
+        assert_eq!(cached(&rig), Some(0.5));
+        // far from the volume, so the fader hasn't picked it up yet
+        rig.send(&[0xb0, 11, 127]);
+        assert_eq!(rig.volume(&spotify()), 0.5);
+        rig.send(&[0xb0, 11, 60]);
+        assert_eq!(rig.volume(&spotify()), 60.0 / 127.0);
+
+        // the volume is read again when the bank changes
+        rig.backend
+            .lock()
+            .unwrap()
+            .set_volume(&spotify(), 0.2)
+            .unwrap();
+        rig.controller.set_bank(u7::from(1)).unwrap();
+        rig.flush();
+        assert_eq!(cached(&rig), Some(0.2));
+        rig.send(&[0xb0, 11, 60]);
+        assert_eq!(rig.volume(&spotify()), 0.2);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod bindings;
+pub mod midi;
+pub mod volume;
+pub mod worker;
@@ -1,79 +1,134 @@
-mod midi;
-mod volume;
-
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::stdin;
+use std::path::Path;
+use std::process::exit;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::{error::Error, io::BufReader};
 
-use midir::{Ignore, MidiInput};
-use midly::num::u7;
-use midly::{live::LiveEvent, MidiMessage};
-
-use midi::{find_in_port, Controller, Preset};
-use volume::{AudioController, CoInitMode};
-
-fn handle_message(message: &[u8], midi_controller: &mut Controller, debug: bool) {
-    let event = LiveEvent::parse(message).unwrap();
-    match event {
-        LiveEvent::Midi {
-            channel: _,
-            message,
-        } => match message {
-            MidiMessage::Controller { controller, value } => {
-                if debug {
-                    println!("Controller {:?}: {:?}", controller, value);
-                }
-                midi_controller.set_component(controller, value);
-            }
-            _ => todo!(),
-        },
-        LiveEvent::Common(sys_common) => match sys_common {
-            midly::live::SystemCommon::SysEx(bytes) => {
-                if debug {
-                    println!("SysEx {:?}", bytes);
-                }
-                midi_controller.set_bank(bytes[bytes.len() - 1]);
-            }
-            _ => todo!(),
-        },
-        _ => todo!(),
+use midir::{Ignore, MidiInput, MidiOutput};
+
+use midlman::bindings::Bindings;
+use midlman::midi::{find_in_port, find_out_port, Controller, MessageError, Preset, SharedSink};
+use midlman::volume::{AudioBackend, MockAudioBackend, SharedBackend};
+use midlman::worker::AudioWorker;
+
+/// What the main thread waits for while the connection is open.
+enum Notice {
+    Error(MessageError),
+    /// Enter was pressed.
+    Quit,
+}
+
+fn get_backend(mock: bool) -> Box<dyn AudioBackend> {
+    if mock {
+        println!("Using the in-memory mock audio backend");
+        return Box::new(
+            MockAudioBackend::new()
+                .with_device("master", 1.0, false)
+                .with_session("Spotify", 1000, 0.5, false),
+        );
+    }
+    get_system_backend()
+}
+
+#[cfg(all(windows, feature = "windows-backend"))]
+fn get_system_backend() -> Box<dyn AudioBackend> {
+    use midlman::volume::{AudioController, CoInitMode};
+
+    Box::new(unsafe { AudioController::new(CoInitMode::MultiThreaded) })
+}
+
+#[cfg(all(target_os = "linux", feature = "pulse-backend"))]
+fn get_system_backend() -> Box<dyn AudioBackend> {
+    Box::new(midlman::volume::PulseBackend::new())
+}
+
+#[cfg(not(any(
+    all(windows, feature = "windows-backend"),
+    all(target_os = "linux", feature = "pulse-backend")
+)))]
+fn get_system_backend() -> Box<dyn AudioBackend> {
+    println!("No audio backend compiled in, MIDI input will not control any sessions");
+    Box::new(MockAudioBackend::new())
+}
+
+fn print_sessions(backend: &SharedBackend) {
+    let sessions = backend.lock().unwrap().sessions().unwrap_or_else(|err| {
+        eprintln!("ERROR: Couldn't enumerate sessions: {err}");
+        Vec::new()
+    });
+    unsafe {
+        println!("Sessions:");
+        sessions.iter().for_each(|x| {
+            println!(
+                "  {}:\n    PID: {},\n    Volume: {}",
+                x.get_name(),
+                x.get_pid(),
+                x.get_volume()
+            );
+        });
+    }
+}
+
+/// Reads and validates a preset, printing every diagnostic as `file:line: ...`.
+fn load_preset(path: &Path) -> Result<Preset, Box<dyn Error>> {
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let preset: Preset =
+        serde_yaml::from_str(&source).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let diagnostics = preset.validate(&source);
+    if !diagnostics.is_empty() {
+        diagnostics
+            .iter()
+            .for_each(|d| eprintln!("{}:{}", path.display(), d));
+        return Err(format!("{} has {} problem(s)", path.display(), diagnostics.len()).into());
     }
+    Ok(preset)
+}
+
+fn validate(path: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(path.ok_or("usage: midlman validate <preset>")?);
+    load_preset(path)?;
+    println!("{} is valid", path.display());
+    Ok(())
 }
 
 fn run() -> Result<(), Box<dyn Error>> {
     let debug = env::args().any(|x| x == "--debug");
+    let mock = env::args().any(|x| x == "--mock");
     // load presets from yaml files
     //
-    let subzero_path = "data/subzero.yaml";
-    let reader = BufReader::new(File::open(subzero_path)?);
+    let subzero_path = Path::new("data/subzero.yaml");
 
     // Select preset
     //
-    let subzero_preset: Preset = serde_yaml::from_reader(reader)?;
+    let subzero_preset = load_preset(subzero_path)?;
 
-    // load configuration from yaml file
+    // load bindings from the yaml file next to the preset
     //
+    let bindings_path = subzero_path.with_file_name("bindings.yaml");
+    let bindings: Bindings = if bindings_path.exists() {
+        serde_yaml::from_reader(BufReader::new(File::open(&bindings_path)?))
+            .map_err(|err| format!("{}: {}", bindings_path.display(), err))?
+    } else {
+        println!("No bindings found at {}", bindings_path.display());
+        Bindings::default()
+    };
 
     // create midi controller with preset and configuration
     //
     // run application
     //
 
-    let audio_controller = unsafe { AudioController::new(CoInitMode::MultiThreaded) };
-    unsafe {
-        println!("Sessions:");
-        audio_controller.sessions.iter().for_each(|x| {
-            println!(
-                "  {}:\n    PID: {},\n    Volume: {}",
-                x.get_name(),
-                x.get_pid(),
-                x.get_volume()
-            );
-        });
-    }
+    // let user select controller from list
+    //
+    let mut controller = Controller::from_preset(subzero_preset)
+        .map_err(|err| format!("{}: {}", subzero_path.display(), err))?;
 
-    let mut input = String::new();
+    let backend: SharedBackend = Arc::new(Mutex::new(get_backend(mock)));
+    print_sessions(&backend);
 
     let mut midi_in = MidiInput::new("midir reading input")?;
     midi_in.ignore(Ignore::None);
@@ -82,43 +137,38 @@ fn run() -> Result<(), Box<dyn Error>> {
     println!("\nOpening connection");
     let in_port_name = midi_in.port_name(&in_port)?;
 
-    // let user select controller from list
+    // send feedback to the output port of the same controller, if it has one
     //
-    let mut controller = Controller::from_preset(subzero_preset);
-
-    let spotify = unsafe { audio_controller.get_session_by_name("Spotify".to_string()) };
-    if let Some(spotify) = spotify {
-        let spotify_clone1 = spotify.clone();
-        println!("Found spotify session");
-        controller.bind_component(
-            u7::from(5),
-            Box::new(move |value| {
-                let normalized = value.as_int() as f32 / 127.0;
-                println!("Setting spotify volume to {}", normalized);
-                // convert to i32, divide by 127
-                unsafe {
-                    spotify_clone1.lock().unwrap().set_volume(normalized);
-                }
-            }),
-        );
-        let spotify_clone2 = spotify.clone();
-        controller.bind_component(
-            u7::from(25),
-            Box::new(move |value| unsafe {
-                if value.as_int() == 127 {
-                    let muted = spotify_clone2.lock().unwrap().get_mute();
-                    println!("Setting spotify mute to {}", !muted);
-                    spotify_clone2.lock().unwrap().set_mute(!muted);
-                }
-            }),
-        );
+    let midi_out = MidiOutput::new("midir feedback output")?;
+    match find_out_port(&midi_out, &in_port_name) {
+        Some(out_port) => {
+            let connection = midi_out.connect(&out_port, "midir-feedback-output")?;
+            let sink: SharedSink = Arc::new(Mutex::new(Box::new(connection)));
+            controller.set_sink(sink);
+        }
+        None => println!(
+            "No output port found for '{}', feedback is off",
+            in_port_name
+        ),
     }
+    // bound components queue their actions for the worker, off the MIDI thread
+    //
+    let worker = AudioWorker::spawn(backend.clone(), debug);
+    bindings.bind(&mut controller, &backend, &worker);
+    controller.sync();
 
+    // the MIDI thread reports what it couldn't handle, and this one prints it
+    //
+    let (notices, notices_rx) = mpsc::channel();
     let _conn_in = midi_in.connect(
         &in_port,
         "midir-read-input",
-        move |_, message, _| handle_message(message, &mut controller, debug),
-        (),
+        move |timestamp, message, notices: &mut mpsc::Sender<Notice>| {
+            if let Err(err) = controller.handle_message(timestamp, message, debug) {
+                let _ = notices.send(Notice::Error(err));
+            }
+        },
+        notices.clone(),
     )?;
 
     println!(
@@ -126,16 +176,50 @@ fn run() -> Result<(), Box<dyn Error>> {
         in_port_name
     );
 
-    input.clear();
-    stdin().read_line(&mut input)?; // wait for next enter key press
+    thread::spawn(move || {
+        let mut input = String::new();
+        let _ = stdin().read_line(&mut input); // wait for next enter key press
+        let _ = notices.send(Notice::Quit);
+    });
+    let mut malformed = 0;
+    for notice in notices_rx {
+        match notice {
+            Notice::Error(err @ MessageError::Parse { .. }) => {
+                malformed += 1;
+                eprintln!("ERROR: {err} ({malformed} so far)");
+            }
+            Notice::Error(err) => eprintln!("ERROR: {err}"),
+            Notice::Quit => break,
+        }
+    }
 
     println!("Closing connection");
+    if malformed > 0 {
+        println!("Ignored {} malformed message(s)", malformed);
+    }
     Ok(())
 }
 
 fn main() {
-    match run() {
-        Ok(_) => (),
-        Err(err) => println!("Error: {}", err),
+    let args: Vec<String> = env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("validate") => validate(args.get(2)),
+        _ => run(),
+    };
+    if let Err(err) = result {
+        println!("Error: {}", err);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(any(
+        all(windows, feature = "windows-backend"),
+        all(target_os = "linux", feature = "pulse-backend")
+    )))]
+    #[test]
+    fn runs_without_an_audio_backend() {
+        assert!(super::get_system_backend().sessions().unwrap().is_empty());
     }
 }
@@ -0,0 +1,135 @@
+use midly::num::u7;
+use serde::Deserialize;
+
+/// Which bank a matched trigger switches to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BankAction {
+    /// The bank after the active one, wrapping to the first.
+    Next,
+    /// The bank before the active one, wrapping to the last.
+    Previous,
+    /// A specific bank.
+    Bank(u8),
+}
+
+/// A MIDI message that selects a bank, as declared in a preset's `bank_triggers`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BankTrigger {
+    /// A SysEx message whose data starts with `prefix`. The bank is the data
+    /// byte at `offset`, counted from the end when negative (`-1` is the last),
+    /// and must follow the prefix. Neither counts the `F0`/`F7` framing bytes.
+    Sysex { prefix: Vec<u8>, offset: isize },
+    /// A Program Change. With `programs`, program `programs[n]` selects bank
+    /// `n`; without, the program number is the bank.
+    ProgramChange {
+        #[serde(default)]
+        programs: Vec<u8>,
+    },
+    /// A Control Change with a non-zero value.
+    Cc { cc: u8, action: BankAction },
+    /// A Note On with a non-zero velocity.
+    Note { note: u8, action: BankAction },
+}
+
+/// The parts of an incoming message a [`BankTrigger`] can match on.
+#[derive(Debug, Clone, Copy)]
+pub enum BankInput<'a> {
+    SysEx(&'a [u7]),
+    ProgramChange(u7),
+    Cc(u7, u7),
+    NoteOn(u7, u7),
+}
+
+impl BankTrigger {
+    /// The bank change `input` asks for, if this trigger matches it.
+    pub fn matches(&self, input: BankInput) -> Option<BankAction> {
+        match (self, input) {
+            (BankTrigger::Sysex { prefix, offset }, BankInput::SysEx(data)) => {
+                let starts_with = data.len() >= prefix.len()
+                    && data.iter().zip(prefix).all(|(d, p)| d.as_int() == *p);
+                if !starts_with {
+                    return None;
+                }
+                let payload = &data[prefix.len()..];
+                let index = match *offset {
+                    offset if offset < 0 => payload.len().checked_sub(offset.unsigned_abs())?,
+                    offset => (offset as usize).checked_sub(prefix.len())?,
+                };
+                payload
+                    .get(index)
+                    .map(|bank| BankAction::Bank(bank.as_int()))
+            }
+            (BankTrigger::ProgramChange { programs }, BankInput::ProgramChange(program)) => {
+                if programs.is_empty() {
+                    return Some(BankAction::Bank(program.as_int()));
+                }
+                programs
+                    .iter()
+                    .position(|p| *p == program.as_int())
+                    .map(|bank| BankAction::Bank(bank as u8))
+            }
+            (BankTrigger::Cc { cc, action }, BankInput::Cc(controller, value)) => {
+                (controller.as_int() == *cc && value.as_int() > 0).then_some(*action)
+            }
+            (BankTrigger::Note { note, action }, BankInput::NoteOn(key, vel)) => {
+                (key.as_int() == *note && vel.as_int() > 0).then_some(*action)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this trigger claims every message on `cc`, pressed or released.
+    pub fn claims_cc(&self, controller: u7) -> bool {
+        matches!(self, BankTrigger::Cc { cc, .. } if *cc == controller.as_int())
+    }
+    /// Whether this trigger claims every Note On/Off on `note`.
+    pub fn claims_note(&self, key: u7) -> bool {
+        matches!(self, BankTrigger::Note { note, .. } if *note == key.as_int())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sysex(prefix: &[u8], offset: isize, data: &[u8]) -> Option<BankAction> {
+        let trigger = BankTrigger::Sysex {
+            prefix: prefix.to_vec(),
+            offset,
+        };
+        let data: Vec<u7> = data.iter().map(|byte| u7::from(*byte)).collect();
+        trigger.matches(BankInput::SysEx(&data))
+    }
+
+    #[test]
+    fn sysex_offsets_count_from_either_end() {
+        let data = [0x00, 0x20, 0x29, 0x02, 0x11, 0x05];
+        assert_eq!(
+            sysex(&[0x00, 0x20, 0x29], 4, &data),
+            Some(BankAction::Bank(0x11))
+        );
+        assert_eq!(
+            sysex(&[0x00, 0x20, 0x29], -1, &data),
+            Some(BankAction::Bank(0x05))
+        );
+        assert_eq!(sysex(&[], -1, &[0x03]), Some(BankAction::Bank(3)));
+        // past either end of the data
+        assert_eq!(sysex(&[0x00, 0x20, 0x29], 6, &data), None);
+        assert_eq!(sysex(&[0x00, 0x20, 0x29], -4, &data), None);
+    }
+
+    #[test]
+    fn sysex_banks_never_come_from_the_prefix() {
+        assert_eq!(sysex(&[0x00, 0x20], -1, &[0x00, 0x20]), None);
+        assert_eq!(sysex(&[0x00, 0x20], 1, &[0x00, 0x20, 0x04]), None);
+        assert_eq!(sysex(&[], -1, &[]), None);
+    }
+
+    #[test]
+    fn sysex_from_other_manufacturers_is_ignored() {
+        assert_eq!(sysex(&[0x00, 0x20, 0x29], -1, &[0x43, 0x10, 0x02]), None);
+        assert_eq!(sysex(&[0x00, 0x20, 0x29], -1, &[0x00, 0x20]), None);
+    }
+}
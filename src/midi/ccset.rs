@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use midly::num::{u14, u7};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CcSetErrorKind {
+    /// The list, or one of its comma-separated entries, is blank.
+    Empty,
+    /// The entry is not a number or a `start-end` range.
+    InvalidNumber,
+    /// The number is above the largest allowed, 127 for CCs.
+    OutOfRange(u32, u16),
+    /// The range end is lower than its start.
+    ReversedRange(u16, u16),
+}
+
+/// A CC list entry that failed to parse, with its byte offset in the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcSetError {
+    pub token: String,
+    pub position: usize,
+    pub kind: CcSetErrorKind,
+}
+
+impl fmt::Display for CcSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' at offset {}: ", self.token, self.position)?;
+        match &self.kind {
+            CcSetErrorKind::Empty => write!(f, "empty entry"),
+            CcSetErrorKind::InvalidNumber => write!(f, "expected a number or a range"),
+            CcSetErrorKind::OutOfRange(n, max) => write!(f, "{} is above {}", n, max),
+            CcSetErrorKind::ReversedRange(start, end) => {
+                write!(f, "range end {} is lower than its start {}", end, start)
+            }
+        }
+    }
+}
+
+impl Error for CcSetError {}
+
+/// A list of CC numbers written as comma-separated values and inclusive
+/// ranges, e.g. `"1, 2, 23-31, 64"`. Order is kept as written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CcSet(Vec<u7>);
+
+impl CcSet {
+    pub fn iter(&self) -> impl Iterator<Item = u7> + '_ {
+        self.0.iter().copied()
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn contains(&self, cc: u7) -> bool {
+        self.0.contains(&cc)
+    }
+
+    /// Parses as much of `s` as possible, returning every valid CC along with
+    /// an error for each invalid entry.
+    pub fn parse_all(s: &str) -> (Self, Vec<CcSetError>) {
+        let (numbers, errors) = parse_numbers(s, 127);
+        (CcSet(numbers.map(|n| u7::new(n as u8)).collect()), errors)
+    }
+}
+
+/// A list of 14-bit parameter numbers, such as NRPNs, written like a
+/// [`CcSet`] but allowing numbers up to 16383.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParameterSet(Vec<u14>);
+
+impl ParameterSet {
+    pub fn iter(&self) -> impl Iterator<Item = u14> + '_ {
+        self.0.iter().copied()
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parses as much of `s` as possible, like [`CcSet::parse_all`].
+    pub fn parse_all(s: &str) -> (Self, Vec<CcSetError>) {
+        let (numbers, errors) = parse_numbers(s, 16383);
+        (ParameterSet(numbers.map(u14::new).collect()), errors)
+    }
+}
+
+fn parse_numbers(s: &str, max: u16) -> (impl Iterator<Item = u16>, Vec<CcSetError>) {
+    let mut ranges = Vec::new();
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    for entry in s.split(',') {
+        let position = offset + (entry.len() - entry.trim_start().len());
+        offset += entry.len() + 1;
+        match parse_entry(entry, position, max) {
+            Ok(range) => ranges.push(range),
+            Err(error) => errors.push(error),
+        }
+    }
+    (ranges.into_iter().flatten(), errors)
+}
+
+fn parse_entry(entry: &str, position: usize, max: u16) -> Result<RangeInclusive<u16>, CcSetError> {
+    let trimmed = entry.trim();
+    if trimmed.is_empty() {
+        return Err(CcSetError {
+            token: entry.to_string(),
+            position,
+            kind: CcSetErrorKind::Empty,
+        });
+    }
+    match trimmed.split_once('-') {
+        Some((start, end)) => {
+            let end_position = position + start.len() + 1;
+            let end_position = end_position + (end.len() - end.trim_start().len());
+            let start = parse_number(start.trim(), position, max)?;
+            let end = parse_number(end.trim(), end_position, max)?;
+            if end < start {
+                return Err(CcSetError {
+                    token: trimmed.to_string(),
+                    position,
+                    kind: CcSetErrorKind::ReversedRange(start, end),
+                });
+            }
+            Ok(start..=end)
+        }
+        None => {
+            let n = parse_number(trimmed, position, max)?;
+            Ok(n..=n)
+        }
+    }
+}
+
+fn parse_number(token: &str, position: usize, max: u16) -> Result<u16, CcSetError> {
+    let error = |kind| CcSetError {
+        token: token.to_string(),
+        position,
+        kind,
+    };
+    let n = token
+        .parse::<u32>()
+        .map_err(|_| error(CcSetErrorKind::InvalidNumber))?;
+    if n > max as u32 {
+        return Err(error(CcSetErrorKind::OutOfRange(n, max)));
+    }
+    Ok(n as u16)
+}
+
+impl FromStr for CcSet {
+    type Err = CcSetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (set, mut errors) = Self::parse_all(s);
+        match errors.is_empty() {
+            true => Ok(set),
+            false => Err(errors.remove(0)),
+        }
+    }
+}
+
+impl FromStr for ParameterSet {
+    type Err = CcSetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (set, mut errors) = Self::parse_all(s);
+        match errors.is_empty() {
+            true => Ok(set),
+            false => Err(errors.remove(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ccs(s: &str) -> Vec<u8> {
+        s.parse::<CcSet>()
+            .unwrap()
+            .iter()
+            .map(|cc| cc.as_int())
+            .collect()
+    }
+
+    fn error(s: &str) -> CcSetError {
+        s.parse::<CcSet>().unwrap_err()
+    }
+
+    #[test]
+    fn single_numbers_and_ranges() {
+        assert_eq!(ccs("64"), [64]);
+        assert_eq!(ccs("1,2,23-25,64"), [1, 2, 23, 24, 25, 64]);
+        assert_eq!(ccs("0-0,127"), [0, 127]);
+    }
+
+    #[test]
+    fn whitespace_around_entries_is_ignored() {
+        assert_eq!(ccs(" 1 ,\t2, 23 - 25 "), [1, 2, 23, 24, 25]);
+    }
+
+    #[test]
+    fn empty_lists_and_entries() {
+        let err = error("");
+        assert_eq!((err.kind, err.position), (CcSetErrorKind::Empty, 0));
+        let err = error("   ");
+        assert_eq!((err.kind, err.position), (CcSetErrorKind::Empty, 3));
+        let err = error("1,,2");
+        assert_eq!((err.kind, err.position), (CcSetErrorKind::Empty, 2));
+    }
+
+    #[test]
+    fn numbers_above_127() {
+        let err = error("1, 128");
+        assert_eq!(err.token, "128");
+        assert_eq!(err.position, 3);
+        assert_eq!(err.kind, CcSetErrorKind::OutOfRange(128, 127));
+        let err = error("100-300");
+        assert_eq!((err.token.as_str(), err.position), ("300", 4));
+        assert_eq!(err.kind, CcSetErrorKind::OutOfRange(300, 127));
+    }
+
+    #[test]
+    fn reversed_ranges() {
+        let err = error("3, 31-23");
+        assert_eq!(err.token, "31-23");
+        assert_eq!(err.position, 3);
+        assert_eq!(err.kind, CcSetErrorKind::ReversedRange(31, 23));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        for (list, token, position) in [("a", "a", 0), ("1, 2x", "2x", 3)] {
+            let err = error(list);
+            assert_eq!(err.kind, CcSetErrorKind::InvalidNumber, "{list}");
+            assert_eq!(
+                (err.token.as_str(), err.position),
+                (token, position),
+                "{list}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let (set, errors) = CcSet::parse_all("1, 200, 5-3, x, 7");
+        assert_eq!(set.iter().map(|cc| cc.as_int()).collect::<Vec<_>>(), [1, 7]);
+        let positions: Vec<_> = errors.iter().map(|e| e.position).collect();
+        assert_eq!(positions, [3, 8, 13]);
+    }
+
+    #[test]
+    fn parameter_numbers_go_up_to_16383() {
+        let set: ParameterSet = "1000-1002, 16383".parse().unwrap();
+        assert_eq!(set.len(), 4);
+        let err = "16384".parse::<ParameterSet>().unwrap_err();
+        assert_eq!(err.kind, CcSetErrorKind::OutOfRange(16384, 16383));
+    }
+}
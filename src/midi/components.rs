@@ -1,29 +1,312 @@
 mod button;
 mod dial;
+mod pad;
 mod slider;
+mod takeover;
 
-use midly::num::u7;
+use std::fmt;
 
-pub use button::Button;
-pub use dial::Dial;
+use midly::num::{u14, u4, u7};
+use serde::Deserialize;
+
+pub use button::{Button, ButtonEvent, ButtonSettings};
+pub use dial::{Acceleration, Dial, EncoderMode};
+pub use pad::Pad;
 pub use slider::Slider;
+pub use takeover::{Takeover, TakeoverMode};
+
+/// What a kind of component does with the values it receives. A new kind
+/// implements this and is added to [`KINDS`], which presets list it by.
+pub trait Behavior: CloneBehavior + Send {
+    /// Sets the value of a message received at `timestamp`, in microseconds.
+    fn set_value_at(&mut self, value: Value, timestamp: u64);
+    /// Whether `event` can be bound. Most components only have presses,
+    /// which is any value they receive.
+    fn has_event(&self, event: ButtonEvent) -> bool {
+        event == ButtonEvent::Press
+    }
+    /// Binds `event`, one the behavior [has](Behavior::has_event).
+    fn set_callback(&mut self, event: ButtonEvent, callback: Box<dyn ComponentCallback>);
+    /// Where soft takeover reads the bound target's current value from, for
+    /// behaviors that have it. Behaviors refresh it straight away, so the
+    /// value is known by the first move.
+    fn set_source(&mut self, _source: Box<dyn ValueSource>) {}
+    /// Forgets the component's position, which may have moved while it
+    /// wasn't in control, and refreshes the source.
+    fn release(&mut self) {}
+    /// `value` as the component reads it, such as the step of an endless
+    /// encoder.
+    fn decode(&self, value: Value) -> Value {
+        value
+    }
+}
+
+pub trait CloneBehavior {
+    fn clone_box(&self) -> Box<dyn Behavior>;
+}
+
+impl<B> CloneBehavior for B
+where
+    B: Behavior + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn Behavior> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Behavior> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+/// How the preset sets up one component, for [`ComponentKind::build`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ComponentSettings {
+    pub takeover: TakeoverMode,
+    pub encoder: EncoderMode,
+    pub acceleration: Option<Acceleration>,
+    pub buttons: ButtonSettings,
+}
 
-pub trait Component {
-    fn get_controller(&self) -> u7;
+/// A kind of component, listed in presets under its `list` name.
+#[derive(Debug)]
+pub struct ComponentKind {
+    /// The key of the kind's list in a preset's `components` and `nrpn`.
+    pub list: &'static str,
+    /// Whether it is addressed by note number rather than CC.
+    pub note: bool,
+    /// Whether it reports a position, so it can be 14-bit and have soft
+    /// takeover.
+    pub absolute: bool,
+    /// Whether it can be an endless encoder.
+    pub encoder: bool,
+    pub build: fn(&ComponentSettings) -> Box<dyn Behavior>,
 }
 
-pub trait ComponentMut: Component {
-    fn set_value(&mut self, value: u7);
+impl ComponentKind {
+    /// The kind listed as `list`.
+    pub fn named(list: &str) -> Option<&'static ComponentKind> {
+        KINDS.iter().find(|kind| kind.list == list)
+    }
+    /// The lists of every kind matching `filter`, e.g. `sliders or dials`.
+    pub fn names(filter: impl Fn(&ComponentKind) -> bool) -> String {
+        let names: Vec<_> = KINDS
+            .iter()
+            .filter(|kind| filter(kind))
+            .map(|kind| kind.list)
+            .collect();
+        match names.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
 }
 
+pub const SLIDER: ComponentKind = ComponentKind {
+    list: "sliders",
+    note: false,
+    absolute: true,
+    encoder: false,
+    build: |settings| Box::new(Slider::new(settings.takeover)),
+};
+
+pub const BUTTON: ComponentKind = ComponentKind {
+    list: "buttons",
+    note: false,
+    absolute: false,
+    encoder: false,
+    build: |settings| Box::new(Button::new(settings.buttons)),
+};
+
+pub const DIAL: ComponentKind = ComponentKind {
+    list: "dials",
+    note: false,
+    absolute: true,
+    encoder: true,
+    build: |settings| {
+        let mut dial = Dial::new(settings.takeover);
+        dial.set_mode(settings.encoder);
+        if let Some(acceleration) = settings.acceleration {
+            dial.set_acceleration(acceleration);
+        }
+        Box::new(dial)
+    },
+};
+
+pub const PAD: ComponentKind = ComponentKind {
+    list: "pads",
+    note: true,
+    absolute: false,
+    encoder: false,
+    build: |_| Box::new(Pad::new()),
+};
+
+/// Every kind of component a preset can list by CC, note or NRPN number, in
+/// the order they are laid out. Pitch-bend faders aren't a kind of their own,
+/// they are [`SLIDER`]s addressed by channel.
+pub static KINDS: &[ComponentKind] = &[SLIDER, BUTTON, DIAL, PAD];
+
+/// A physical control: where it listens and how it behaves.
 #[derive(Clone)]
-pub enum ComponentType {
-    Slider,
-    Button,
-    Dial,
+pub struct Component {
+    /// The channel the component listens on, `None` for any channel.
+    channel: Option<u4>,
+    address: Address,
+    behavior: Box<dyn Behavior>,
+}
+
+impl Component {
+    pub fn new(channel: Option<u4>, address: Address, behavior: Box<dyn Behavior>) -> Self {
+        Self {
+            channel,
+            address,
+            behavior,
+        }
+    }
+    pub fn channel(&self) -> Option<u4> {
+        self.channel
+    }
+    pub fn address(&self) -> Address {
+        self.address
+    }
+    pub fn decode(&self, value: Value) -> Value {
+        self.behavior.decode(value)
+    }
+    pub fn set_value_at(&mut self, value: Value, timestamp: u64) {
+        self.behavior.set_value_at(value, timestamp);
+    }
+    pub fn has_event(&self, event: ButtonEvent) -> bool {
+        self.behavior.has_event(event)
+    }
+    /// Binds `event`, ignoring events the component doesn't have.
+    pub fn set_callback(&mut self, event: ButtonEvent, callback: Box<dyn ComponentCallback>) {
+        if self.has_event(event) {
+            self.behavior.set_callback(event, callback);
+        }
+    }
+    pub fn set_source(&mut self, source: Box<dyn ValueSource>) {
+        self.behavior.set_source(source);
+    }
+    pub fn release(&mut self) {
+        self.behavior.release();
+    }
+}
+
+/// A component value at the resolution it was received in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    /// A plain 7-bit CC, note velocity, etc.
+    U7(u7),
+    /// A 14-bit value, from a CC MSB/LSB pair, pitch bend or NRPN.
+    U14(u14),
+    /// A signed step from an endless encoder, rather than a position.
+    Relative(i16),
+}
+
+impl Value {
+    /// The value as an integer, or the size of the step for a relative value.
+    pub fn as_int(self) -> u16 {
+        match self {
+            Value::U7(value) => value.as_int() as u16,
+            Value::U14(value) => value.as_int(),
+            Value::Relative(step) => step.unsigned_abs(),
+        }
+    }
+    pub fn max(self) -> u16 {
+        match self {
+            Value::U7(_) | Value::Relative(_) => 127,
+            Value::U14(_) => 16383,
+        }
+    }
+    /// The value scaled to `0.0..=1.0`.
+    pub fn normalized(self) -> f32 {
+        self.as_int() as f32 / self.max() as f32
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Relative(step) => write!(f, "{:+}", step),
+            _ => write!(f, "{}", self.as_int()),
+        }
+    }
+}
+
+/// The MIDI message a component listens to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Address {
+    Cc(u7),
+    Note(u7),
+    /// A pitch-bend fader, by channel.
+    PitchBend(u4),
+    Nrpn(u14),
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Cc(cc) => write!(f, "CC {}", cc),
+            Address::Note(note) => write!(f, "note {}", note),
+            Address::PitchBend(channel) => write!(f, "pitch bend {}", channel.as_int() + 1),
+            Address::Nrpn(number) => write!(f, "NRPN {}", number),
+        }
+    }
 }
 
-pub trait ComponentCallback: Fn(u7) + Send {
+/// A component as written in bindings and presets: an optional channel
+/// and exactly one of `cc`, `note`, `pitchbend` or `nrpn`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComponentRef {
+    /// Channel (1-16) of the component, for presets that repeat a number
+    /// across channels. Without one, it matches the number on any channel.
+    #[serde(default)]
+    pub channel: Option<u8>,
+    #[serde(default)]
+    pub cc: Option<u8>,
+    #[serde(default)]
+    pub note: Option<u8>,
+    /// Channel (1-16) of a pitch-bend fader.
+    #[serde(default)]
+    pub pitchbend: Option<u8>,
+    #[serde(default)]
+    pub nrpn: Option<u16>,
+}
+
+impl ComponentRef {
+    pub fn channel(&self) -> Result<Option<u4>, String> {
+        match self.channel {
+            None => Ok(None),
+            Some(channel @ 1..=16) => Ok(Some(u4::from(channel - 1))),
+            Some(channel) => Err(format!("channel {} is not between 1 and 16", channel)),
+        }
+    }
+    pub fn address(&self) -> Result<Address, String> {
+        match (self.cc, self.note, self.pitchbend, self.nrpn) {
+            (Some(cc), None, None, None) => u7::try_from(cc)
+                .map(Address::Cc)
+                .ok_or_else(|| format!("CC {} is out of range", cc)),
+            (None, Some(note), None, None) => u7::try_from(note)
+                .map(Address::Note)
+                .ok_or_else(|| format!("note {} is out of range", note)),
+            (None, None, Some(channel @ 1..=16), None) => {
+                Ok(Address::PitchBend(u4::from(channel - 1)))
+            }
+            (None, None, Some(channel), None) => Err(format!(
+                "pitch bend channel {} is not between 1 and 16",
+                channel
+            )),
+            (None, None, None, Some(nrpn)) => u14::try_from(nrpn)
+                .map(Address::Nrpn)
+                .ok_or_else(|| format!("NRPN {} is out of range", nrpn)),
+            _ => Err("a component needs exactly one of cc, note, pitchbend or nrpn".to_string()),
+        }
+    }
+}
+
+pub trait ComponentCallback: Fn(Value) + Send {
     fn clone_box<'a>(&self) -> Box<dyn 'a + ComponentCallback>
     where
         Self: 'a;
@@ -31,7 +314,7 @@ pub trait ComponentCallback: Fn(u7) + Send {
 
 impl<F> ComponentCallback for F
 where
-    F: Fn(u7) + Clone + Send,
+    F: Fn(Value) + Clone + Send,
 {
     fn clone_box<'a>(&self) -> Box<dyn 'a + ComponentCallback>
     where
@@ -46,3 +329,22 @@ impl<'a> Clone for Box<dyn 'a + ComponentCallback> {
         (**self).clone_box()
     }
 }
+
+/// Reads the current value (`0.0..=1.0`) of what a component controls, for
+/// soft takeover.
+pub trait ValueSource: Send {
+    /// The last value read, or `None` if it hasn't been read yet.
+    fn value(&self) -> Option<f32>;
+    /// Reads the value again in the background, for later calls to
+    /// [`ValueSource::value`].
+    fn refresh(&self);
+    fn clone_box<'a>(&self) -> Box<dyn 'a + ValueSource>
+    where
+        Self: 'a;
+}
+
+impl<'a> Clone for Box<dyn 'a + ValueSource> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
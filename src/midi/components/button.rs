@@ -1,40 +1,264 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use midly::num::u7;
+use serde::Deserialize;
+
+use crate::midi::components::Value;
+
+use super::{Behavior, ComponentCallback};
+
+/// What a button press or release means, each bindable separately.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonEvent {
+    /// The button went down, with the value it sent.
+    #[default]
+    Press,
+    /// The button came up, with the value it sent.
+    Release,
+    /// Every press flips a latched state, delivered as 127 (on) or 0 (off).
+    Toggle,
+    /// The button has been held for the long press time.
+    LongPress,
+    /// A second press within the double press time of the first. When
+    /// bound, that second press fires neither a press nor a toggle.
+    DoublePress,
+}
+
+impl fmt::Display for ButtonEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ButtonEvent::Press => "press",
+            ButtonEvent::Release => "release",
+            ButtonEvent::Toggle => "toggle",
+            ButtonEvent::LongPress => "long_press",
+            ButtonEvent::DoublePress => "double_press",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A long press waiting for its hold time.
+struct PendingLongPress {
+    deadline: Instant,
+    /// The button's press counter, still at `press` if it is held.
+    presses: Arc<AtomicU64>,
+    press: u64,
+    callback: Box<dyn ComponentCallback>,
+    value: Value,
+}
 
-use crate::midi::components::{Component, ComponentMut};
+/// Queues a long press on the timer thread shared by every button, started
+/// with the first one.
+fn queue_long_press(pending: PendingLongPress) {
+    static TIMER: OnceLock<Sender<PendingLongPress>> = OnceLock::new();
+    let timer = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<PendingLongPress>();
+        thread::spawn(move || {
+            let mut queue: Vec<PendingLongPress> = Vec::new();
+            loop {
+                let next = queue.iter().map(|pending| pending.deadline).min();
+                let received = match next {
+                    Some(deadline) => {
+                        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok(pending) => queue.push(pending),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+                let now = Instant::now();
+                queue.retain(|pending| {
+                    if pending.deadline > now {
+                        return true;
+                    }
+                    if pending.presses.load(Ordering::SeqCst) == pending.press {
+                        (pending.callback)(pending.value);
+                    }
+                    false
+                });
+            }
+        });
+        sender
+    });
+    // the timer thread only stops with the process
+    let _ = timer.send(pending);
+}
 
-use super::ComponentCallback;
+/// How buttons read presses, shared by every button in a preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ButtonSettings {
+    /// The lowest value (1-127) that counts as pressed, for controllers
+    /// that send velocity or 1/0 rather than 127/0.
+    pub threshold: u8,
+    pub long_press_ms: u64,
+    pub double_press_ms: u64,
+}
 
+impl Default for ButtonSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1,
+            long_press_ms: 500,
+            double_press_ms: 300,
+        }
+    }
+}
+
+/// A button, reporting presses, releases and the events they make up.
 #[derive(Clone)]
 pub struct Button {
-    controller: u7,
-    callback: Option<Box<dyn ComponentCallback>>,
+    settings: ButtonSettings,
+    latched: bool,
+    last_press: Option<Instant>,
+    /// Bumped on every press and release, so a pending long press can tell
+    /// whether the button is still held.
+    presses: Arc<AtomicU64>,
+    callbacks: HashMap<ButtonEvent, Box<dyn ComponentCallback>>,
 }
 
 impl Button {
-    pub fn new(controller: u7) -> Button {
+    pub fn new(settings: ButtonSettings) -> Button {
         Button {
-            controller,
-            callback: None,
+            settings,
+            latched: false,
+            last_press: None,
+            presses: Arc::new(AtomicU64::new(0)),
+            callbacks: HashMap::new(),
         }
     }
-    pub fn set_callback(&mut self, callback: Box<dyn ComponentCallback>) {
-        self.callback = Some(callback);
-    }
-    pub fn invoke_callback(&self, value: u7) {
-        if let Some(callback) = &self.callback {
+    pub fn invoke_callback(&self, event: ButtonEvent, value: Value) {
+        if let Some(callback) = self.callbacks.get(&event) {
             callback(value);
         }
     }
+    fn press(&mut self, value: Value) {
+        let press = self.presses.fetch_add(1, Ordering::SeqCst) + 1;
+        let now = Instant::now();
+        let window = Duration::from_millis(self.settings.double_press_ms);
+        let double = match self.last_press {
+            Some(last) if now.duration_since(last) <= window => {
+                self.last_press = None;
+                true
+            }
+            _ => {
+                self.last_press = Some(now);
+                false
+            }
+        };
+        if double && self.callbacks.contains_key(&ButtonEvent::DoublePress) {
+            self.invoke_callback(ButtonEvent::DoublePress, value);
+        } else {
+            self.invoke_callback(ButtonEvent::Press, value);
+            self.latched = !self.latched;
+            let latched = u7::from(if self.latched { 127 } else { 0 });
+            self.invoke_callback(ButtonEvent::Toggle, Value::U7(latched));
+        }
+        if let Some(callback) = self.callbacks.get(&ButtonEvent::LongPress).cloned() {
+            queue_long_press(PendingLongPress {
+                deadline: now + Duration::from_millis(self.settings.long_press_ms),
+                presses: self.presses.clone(),
+                press,
+                callback,
+                value,
+            });
+        }
+    }
 }
 
-impl Component for Button {
-    fn get_controller(&self) -> u7 {
-        self.controller
+impl Behavior for Button {
+    fn set_value_at(&mut self, value: Value, _timestamp: u64) {
+        if value.normalized() >= self.settings.threshold as f32 / 127.0 {
+            self.press(value);
+        } else {
+            self.presses.fetch_add(1, Ordering::SeqCst);
+            self.invoke_callback(ButtonEvent::Release, value);
+        }
+    }
+    fn has_event(&self, _event: ButtonEvent) -> bool {
+        true
+    }
+    fn set_callback(&mut self, event: ButtonEvent, callback: Box<dyn ComponentCallback>) {
+        self.callbacks.insert(event, callback);
     }
 }
 
-impl ComponentMut for Button {
-    fn set_value(&mut self, value: u7) {
-        self.invoke_callback(value);
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Receiver;
+
+    use super::*;
+
+    const DOWN: Value = Value::U7(u7::new(127));
+    const UP: Value = Value::U7(u7::new(0));
+
+    fn bound(events: &[ButtonEvent]) -> (Button, Receiver<(ButtonEvent, Value)>) {
+        let mut button = Button::new(ButtonSettings {
+            long_press_ms: 20,
+            double_press_ms: 1000,
+            ..ButtonSettings::default()
+        });
+        let (sender, receiver) = mpsc::channel();
+        for &event in events {
+            let sender = sender.clone();
+            button.set_callback(
+                event,
+                Box::new(move |value| sender.send((event, value)).unwrap()),
+            );
+        }
+        (button, receiver)
+    }
+
+    #[test]
+    fn double_press_replaces_the_second_press() {
+        let (mut button, events) = bound(&[ButtonEvent::Press, ButtonEvent::DoublePress]);
+        for value in [DOWN, UP, DOWN, UP, DOWN] {
+            button.set_value_at(value, 0);
+        }
+        let fired: Vec<_> = events.try_iter().map(|(event, _)| event).collect();
+        assert_eq!(
+            fired,
+            [
+                ButtonEvent::Press,
+                ButtonEvent::DoublePress,
+                ButtonEvent::Press
+            ]
+        );
+
+        // without a double press binding, every press is a press
+        let (mut button, events) = bound(&[ButtonEvent::Press]);
+        for value in [DOWN, UP, DOWN] {
+            button.set_value_at(value, 0);
+        }
+        assert_eq!(events.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn long_press_fires_only_while_held() {
+        let (mut held, held_events) = bound(&[ButtonEvent::LongPress]);
+        let (mut tapped, tapped_events) = bound(&[ButtonEvent::LongPress]);
+        held.set_value_at(DOWN, 0);
+        tapped.set_value_at(DOWN, 0);
+        tapped.set_value_at(UP, 0);
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            held_events.recv_timeout(timeout),
+            Ok((ButtonEvent::LongPress, DOWN))
+        );
+        held.set_value_at(UP, 0);
+        assert!(held_events
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+        assert!(tapped_events.try_recv().is_err());
     }
 }
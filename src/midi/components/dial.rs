@@ -1,40 +1,259 @@
-use midly::num::u7;
+use std::fmt;
 
-use crate::midi::components::{Component, ComponentMut};
+use serde::Deserialize;
 
-use super::ComponentCallback;
+use crate::midi::components::Value;
 
+use super::{Behavior, ButtonEvent, ComponentCallback, Slider, TakeoverMode, ValueSource};
+
+/// How a dial encodes its value. Endless encoders send a step relative to
+/// their last position rather than a position. 14-bit values, such as from
+/// NRPN encoders, use the same encodings over 14 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderMode {
+    /// 0-127, passed through like a slider.
+    #[default]
+    Absolute,
+    /// 1-63 up, 127 down to 64 as -1 to -64.
+    TwosComplement,
+    /// 64 is no change, 65 is +1, 63 is -1.
+    BinaryOffset,
+    /// Bit 6 is the sign, 1-63 up and 65-127 down.
+    SignMagnitude,
+}
+
+impl EncoderMode {
+    /// The signed step in a relative `value`, or `None` in absolute mode or
+    /// for a value that is already relative.
+    pub fn decode(self, value: Value) -> Option<i16> {
+        // the sign bit, 64 for 7-bit values
+        let half = match value {
+            Value::U7(_) | Value::U14(_) => (value.max() as i16 + 1) / 2,
+            Value::Relative(_) => return None,
+        };
+        let value = value.as_int() as i16;
+        match self {
+            EncoderMode::Absolute => None,
+            EncoderMode::TwosComplement if value >= half => Some(value - 2 * half),
+            EncoderMode::TwosComplement => Some(value),
+            EncoderMode::BinaryOffset => Some(value - half),
+            EncoderMode::SignMagnitude if value & half != 0 => Some(-(value & (half - 1))),
+            EncoderMode::SignMagnitude => Some(value),
+        }
+    }
+}
+
+impl fmt::Display for EncoderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EncoderMode::Absolute => "absolute",
+            EncoderMode::TwosComplement => "twos_complement",
+            EncoderMode::BinaryOffset => "binary_offset",
+            EncoderMode::SignMagnitude => "sign_magnitude",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Scales the steps of an endless encoder by how fast it is turned. Steps
+/// `slow_ms` or more apart are left as they are, steps `fast_ms` or less
+/// apart are multiplied by `max`, and `curve` shapes the ramp in between
+/// (`1.0` is linear, higher values keep medium speeds finer).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Acceleration {
+    pub slow_ms: u32,
+    pub fast_ms: u32,
+    pub max: f32,
+    pub curve: f32,
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self {
+            slow_ms: 100,
+            fast_ms: 10,
+            max: 8.0,
+            curve: 1.0,
+        }
+    }
+}
+
+impl Acceleration {
+    /// What is wrong with the curve, if anything.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.fast_ms >= self.slow_ms {
+            problems.push(format!(
+                "fast_ms ({}) must be below slow_ms ({})",
+                self.fast_ms, self.slow_ms
+            ));
+        }
+        if self.max < 1.0 {
+            problems.push(format!("max {} is below 1", self.max));
+        }
+        if self.curve <= 0.0 {
+            problems.push(format!("curve {} must be above 0", self.curve));
+        }
+        problems
+    }
+    /// The multiplier for a step arriving `elapsed_us` after the previous one.
+    pub fn factor(&self, elapsed_us: u64) -> f32 {
+        let elapsed = elapsed_us as f32 / 1000.0;
+        let (slow, fast) = (self.slow_ms as f32, self.fast_ms as f32);
+        if elapsed >= slow || slow <= fast {
+            return 1.0;
+        }
+        let speed = ((slow - elapsed) / (slow - fast)).min(1.0);
+        1.0 + (self.max - 1.0) * speed.powf(self.curve)
+    }
+}
+
+/// A knob, absolute like a [`Slider`] or an endless encoder.
 #[derive(Clone)]
 pub struct Dial {
-    controller: u7,
-    callback: Option<Box<dyn ComponentCallback>>,
+    mode: EncoderMode,
+    acceleration: Option<Acceleration>,
+    /// When the last step arrived, for acceleration.
+    last_step: Option<u64>,
+    /// Handles absolute values and holds the callback.
+    fader: Slider,
 }
 
 impl Dial {
-    pub fn new(controller: u7) -> Self {
+    pub fn new(takeover: TakeoverMode) -> Self {
         Self {
-            controller,
-            callback: None,
+            mode: EncoderMode::Absolute,
+            acceleration: None,
+            last_step: None,
+            fader: Slider::new(takeover),
         }
     }
-    pub fn set_callback(&mut self, callback: Box<dyn ComponentCallback>) {
-        self.callback = Some(callback);
+    pub fn set_mode(&mut self, mode: EncoderMode) {
+        self.mode = mode;
     }
-    pub fn invoke_callback(&self, value: u7) {
-        if let Some(callback) = &self.callback {
-            callback(value);
+    pub fn set_acceleration(&mut self, acceleration: Acceleration) {
+        self.acceleration = Some(acceleration);
+    }
+    fn set_value(&mut self, value: Value) {
+        match self.mode.decode(value) {
+            Some(0) => (),
+            Some(step) => self.fader.invoke_callback(Value::Relative(step)),
+            None => self.fader.set_value(value),
         }
     }
 }
 
-impl Component for Dial {
-    fn get_controller(&self) -> u7 {
-        self.controller
+impl Behavior for Dial {
+    fn set_value_at(&mut self, value: Value, timestamp: u64) {
+        let Some(acceleration) = self.acceleration else {
+            return self.set_value(value);
+        };
+        let Some(step) = self.mode.decode(value).filter(|step| *step != 0) else {
+            return self.set_value(value);
+        };
+        // a timestamp before the last one, such as after the clock wrapped,
+        // says nothing about speed
+        let factor = self
+            .last_step
+            .and_then(|last| timestamp.checked_sub(last))
+            .map_or(1.0, |elapsed| acceleration.factor(elapsed));
+        self.last_step = Some(timestamp);
+        self.fader
+            .invoke_callback(Value::Relative((step as f32 * factor).round() as i16));
+    }
+    fn set_callback(&mut self, event: ButtonEvent, callback: Box<dyn ComponentCallback>) {
+        self.fader.set_callback(event, callback);
+    }
+    fn set_source(&mut self, source: Box<dyn ValueSource>) {
+        self.fader.set_source(source);
+    }
+    fn release(&mut self) {
+        self.fader.release();
+    }
+    fn decode(&self, value: Value) -> Value {
+        self.mode.decode(value).map_or(value, Value::Relative)
     }
 }
 
-impl ComponentMut for Dial {
-    fn set_value(&mut self, value: u7) {
-        self.invoke_callback(value);
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use midly::num::{u14, u7};
+
+    use super::*;
+
+    /// A binary offset encoder with the default acceleration, and the steps
+    /// it delivers.
+    fn encoder() -> (Dial, Arc<Mutex<Vec<Value>>>) {
+        let mut dial = Dial::new(TakeoverMode::Jump);
+        dial.set_mode(EncoderMode::BinaryOffset);
+        dial.set_acceleration(Acceleration::default());
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let sink = steps.clone();
+        let callback = move |value: Value| sink.lock().unwrap().push(value);
+        dial.set_callback(ButtonEvent::Press, Box::new(callback));
+        (dial, steps)
+    }
+
+    #[test]
+    fn acceleration_ramps_between_slow_and_fast() {
+        let linear = Acceleration::default();
+        assert_eq!(linear.factor(100_000), 1.0);
+        assert_eq!(linear.factor(1_000_000), 1.0);
+        assert_eq!(linear.factor(10_000), 8.0);
+        assert_eq!(linear.factor(0), 8.0);
+        assert!((linear.factor(55_000) - 4.5).abs() < 1e-4);
+        let curved = Acceleration {
+            curve: 2.0,
+            ..linear
+        };
+        assert!((curved.factor(55_000) - 2.75).abs() < 1e-4);
+        // a curve with no room between slow and fast never accelerates
+        let flat = Acceleration {
+            fast_ms: 100,
+            ..linear
+        };
+        assert_eq!(flat.factor(0), 1.0);
+    }
+
+    #[test]
+    fn acceleration_follows_timestamps() {
+        let (mut dial, steps) = encoder();
+        let step = Value::U7(u7::from(65));
+        // the first step has nothing to compare against
+        dial.set_value_at(step, 5_000_000);
+        // steps at the same time are as fast as it gets
+        dial.set_value_at(step, 5_000_000);
+        dial.set_value_at(step, 5_200_000);
+        // an earlier timestamp, after the clock wrapped, isn't accelerated
+        dial.set_value_at(step, 1_000);
+        dial.set_value_at(step, 11_000);
+        assert_eq!(*steps.lock().unwrap(), [1, 8, 1, 1, 8].map(Value::Relative));
+    }
+
+    #[test]
+    fn encoders_decode_7_and_14_bit_steps() {
+        let u7 = |value: u8| Value::U7(u7::from(value));
+        let u14 = |value: u16| Value::U14(u14::from(value));
+        let cases = [
+            (EncoderMode::TwosComplement, u7(1), 1),
+            (EncoderMode::TwosComplement, u7(127), -1),
+            (EncoderMode::BinaryOffset, u7(65), 1),
+            (EncoderMode::BinaryOffset, u7(63), -1),
+            (EncoderMode::SignMagnitude, u7(65), -1),
+            (EncoderMode::TwosComplement, u14(1), 1),
+            (EncoderMode::TwosComplement, u14(16383), -1),
+            (EncoderMode::BinaryOffset, u14(8193), 1),
+            (EncoderMode::BinaryOffset, u14(8190), -2),
+            (EncoderMode::SignMagnitude, u14(8195), -3),
+            (EncoderMode::SignMagnitude, u14(3), 3),
+        ];
+        for (mode, value, step) in cases {
+            assert_eq!(mode.decode(value), Some(step), "{} {:?}", mode, value);
+        }
+        assert_eq!(EncoderMode::Absolute.decode(u14(8193)), None);
     }
 }
@@ -0,0 +1,26 @@
+use crate::midi::components::Value;
+
+use super::{Behavior, ButtonEvent, ComponentCallback};
+
+/// A velocity-sensitive pad, delivering the velocity of every hit.
+#[derive(Clone, Default)]
+pub struct Pad {
+    callback: Option<Box<dyn ComponentCallback>>,
+}
+
+impl Pad {
+    pub fn new() -> Pad {
+        Pad::default()
+    }
+}
+
+impl Behavior for Pad {
+    fn set_value_at(&mut self, value: Value, _timestamp: u64) {
+        if let Some(callback) = &self.callback {
+            callback(value);
+        }
+    }
+    fn set_callback(&mut self, _event: ButtonEvent, callback: Box<dyn ComponentCallback>) {
+        self.callback = Some(callback);
+    }
+}
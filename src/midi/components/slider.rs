@@ -1,40 +1,66 @@
-use midly::num::u7;
+use crate::midi::components::Value;
 
-use crate::midi::components::{Component, ComponentMut};
+use super::{Behavior, ButtonEvent, ComponentCallback, Takeover, TakeoverMode, ValueSource};
 
-use super::ComponentCallback;
-
-#[derive(Clone)]
+/// An absolute fader or knob.
+#[derive(Clone, Default)]
 pub struct Slider {
-    controller: u7,
+    takeover: Takeover,
+    source: Option<Box<dyn ValueSource>>,
     callback: Option<Box<dyn ComponentCallback>>,
 }
 
 impl Slider {
-    pub fn new(controller: u7) -> Slider {
+    pub fn new(takeover: TakeoverMode) -> Slider {
         Slider {
-            controller,
+            takeover: Takeover::new(takeover),
+            source: None,
             callback: None,
         }
     }
-    pub fn set_callback(&mut self, callback: Box<dyn ComponentCallback>) {
-        self.callback = Some(callback);
-    }
-    pub fn invoke_callback(&self, value: u7) {
+    pub fn invoke_callback(&self, value: Value) {
         if let Some(callback) = &self.callback {
             callback(value);
         }
     }
-}
-
-impl Component for Slider {
-    fn get_controller(&self) -> u7 {
-        self.controller
+    /// Invokes the callback with an absolute `value` once the component has
+    /// taken over the target. Without a source there is nothing to take
+    /// over, and values are passed through.
+    pub fn set_value(&mut self, value: Value) {
+        let value = match &self.source {
+            Some(source) if self.takeover.mode() != TakeoverMode::Jump => {
+                self.takeover.apply(value, source.value())
+            }
+            _ => Some(value),
+        };
+        if let Some(value) = value {
+            self.invoke_callback(value);
+        }
+    }
+    /// Has the source read the target's value again, so it is known by the
+    /// time the component is first moved.
+    fn prefetch(&self) {
+        if let (Some(source), mode) = (&self.source, self.takeover.mode()) {
+            if mode != TakeoverMode::Jump {
+                source.refresh();
+            }
+        }
     }
 }
 
-impl ComponentMut for Slider {
-    fn set_value(&mut self, value: u7) {
-        self.invoke_callback(value);
+impl Behavior for Slider {
+    fn set_value_at(&mut self, value: Value, _timestamp: u64) {
+        self.set_value(value);
+    }
+    fn set_callback(&mut self, _event: ButtonEvent, callback: Box<dyn ComponentCallback>) {
+        self.callback = Some(callback);
+    }
+    fn set_source(&mut self, source: Box<dyn ValueSource>) {
+        self.source = Some(source);
+        self.prefetch();
+    }
+    fn release(&mut self) {
+        self.takeover.release();
+        self.prefetch();
     }
 }
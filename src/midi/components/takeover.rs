@@ -0,0 +1,171 @@
+use std::fmt;
+
+use midly::num::u14;
+use serde::Deserialize;
+
+use crate::midi::components::Value;
+
+/// How close (in `0.0..=1.0`) a fader has to be to the target's value for
+/// [`TakeoverMode::Pickup`] to take over without crossing it.
+const PICKUP_TOLERANCE: f32 = 0.02;
+
+/// What an absolute component does when its position doesn't match the
+/// value of what it controls, e.g. after a bank switch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TakeoverMode {
+    /// Sets the target to the component's position straight away.
+    #[default]
+    Jump,
+    /// Ignores the component until it reaches or crosses the target's value.
+    Pickup,
+    /// Moves the target in proportion to the component's remaining travel,
+    /// so both meet at the end of the range.
+    Scale,
+}
+
+impl fmt::Display for TakeoverMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TakeoverMode::Jump => "jump",
+            TakeoverMode::Pickup => "pickup",
+            TakeoverMode::Scale => "scale",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Soft takeover state of one component: its mode, last position and
+/// whether it is in control of the target.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Takeover {
+    mode: TakeoverMode,
+    last: Option<f32>,
+    /// Set once the component has taken over, after which it follows the
+    /// component without comparing against the target's value, which lags
+    /// behind fast moves.
+    in_control: bool,
+}
+
+impl Takeover {
+    pub fn new(mode: TakeoverMode) -> Self {
+        Self {
+            mode,
+            last: None,
+            in_control: false,
+        }
+    }
+    pub fn mode(&self) -> TakeoverMode {
+        self.mode
+    }
+    /// Gives up control and forgets the component's position, for when it
+    /// may have moved while it wasn't in control, such as on another bank.
+    pub fn release(&mut self) {
+        self.last = None;
+        self.in_control = false;
+    }
+    /// The value to deliver for `value` when the target is at `current`, or
+    /// `None` while the component hasn't taken over. Relative values are
+    /// passed through. A target whose value isn't known yet can't be taken
+    /// over, except in [`TakeoverMode::Jump`].
+    pub fn apply(&mut self, value: Value, current: Option<f32>) -> Option<Value> {
+        if matches!(value, Value::Relative(_)) {
+            return Some(value);
+        }
+        let position = value.normalized();
+        let last = self.last.replace(position);
+        if self.in_control {
+            return Some(value);
+        }
+        let Some(current) = current else {
+            self.in_control = self.mode == TakeoverMode::Jump;
+            return self.in_control.then_some(value);
+        };
+        // a component that was in control left the target at its last
+        // position, give or take the backend's rounding
+        let near = |position: f32| (position - current).abs() <= PICKUP_TOLERANCE;
+        match self.mode {
+            TakeoverMode::Jump => {
+                self.in_control = true;
+                Some(value)
+            }
+            TakeoverMode::Pickup => {
+                let crossed = last.is_some_and(|last| {
+                    near(last) || (last - current) * (position - current) <= 0.0
+                });
+                self.in_control = crossed || near(position);
+                self.in_control.then_some(value)
+            }
+            TakeoverMode::Scale if near(position) => {
+                self.in_control = true;
+                Some(value)
+            }
+            TakeoverMode::Scale => {
+                let last = last?;
+                let scaled = if position > last && last < 1.0 {
+                    current + (position - last) * (1.0 - current) / (1.0 - last)
+                } else if position < last && last > 0.0 {
+                    current - (last - position) * current / last
+                } else {
+                    return None;
+                };
+                let scaled = scaled.clamp(0.0, 1.0);
+                self.in_control = (scaled - position).abs() <= PICKUP_TOLERANCE;
+                let scaled = (scaled * 16383.0).round() as u16;
+                Some(Value::U14(u14::from(scaled)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::num::u7;
+
+    use super::*;
+
+    fn at(position: u8) -> Value {
+        Value::U7(u7::from(position))
+    }
+
+    #[test]
+    fn pickup_follows_once_taken_over() {
+        let mut takeover = Takeover::new(TakeoverMode::Pickup);
+        assert_eq!(takeover.apply(at(20), Some(0.5)), None);
+        assert_eq!(takeover.apply(at(40), Some(0.5)), None);
+        // crossing the target takes it over
+        assert_eq!(takeover.apply(at(80), Some(0.5)), Some(at(80)));
+        // the target's value hasn't caught up with the component yet
+        assert_eq!(takeover.apply(at(120), Some(0.5)), Some(at(120)));
+        assert_eq!(takeover.apply(at(10), Some(0.6)), Some(at(10)));
+
+        takeover.release();
+        // until the target's value is known, nothing is taken over
+        assert_eq!(takeover.apply(at(127), None), None);
+        assert_eq!(takeover.apply(at(100), Some(0.5)), None);
+        assert_eq!(takeover.apply(at(64), Some(0.5)), Some(at(64)));
+    }
+
+    #[test]
+    fn external_changes_apply_once_released() {
+        let mut takeover = Takeover::new(TakeoverMode::Pickup);
+        assert_eq!(takeover.apply(at(64), Some(0.5)), Some(at(64)));
+        // something else set the target, the component stays in control
+        assert_eq!(takeover.apply(at(70), Some(0.9)), Some(at(70)));
+        // until a bank or layer change releases it
+        takeover.release();
+        assert_eq!(takeover.apply(at(72), Some(0.9)), None);
+        assert_eq!(takeover.apply(at(120), Some(0.9)), Some(at(120)));
+    }
+
+    #[test]
+    fn scale_follows_once_met() {
+        let mut takeover = Takeover::new(TakeoverMode::Scale);
+        assert_eq!(takeover.apply(at(0), Some(0.5)), None);
+        let Some(Value::U14(scaled)) = takeover.apply(at(127), Some(0.5)) else {
+            panic!("a scaled value was expected");
+        };
+        assert_eq!(scaled.as_int(), 16383);
+        assert_eq!(takeover.apply(at(20), Some(1.0)), Some(at(20)));
+    }
+}
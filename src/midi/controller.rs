@@ -1,212 +1,875 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
-use midly::num::u7;
+use midly::num::{u14, u4, u7};
 
-use crate::midi::components::{Button, Component, ComponentMut, ComponentType, Dial, Slider};
-use crate::midi::preset::Preset;
+use crate::midi::bankswitch::{BankAction, BankInput, BankTrigger};
+use crate::midi::components::{Address, ButtonEvent, Component, Value, ValueSource};
+use crate::midi::feedback::{Feedback, FeedbackCallback, FeedbackState, SharedSink};
+use crate::midi::layout::{Layout, Route};
+use crate::midi::parameter::{Parameter, ParameterInput, ParameterKind, ParameterParser};
+use crate::midi::preset::{BankPolicy, Preset, PresetError};
 
 use super::components::ComponentCallback;
 
+/// The feedback callback of each bound component in one bank.
+type Syncs = HashMap<(Option<u4>, Address), Box<dyn FeedbackCallback>>;
+
+/// A bank switch that the preset's [`BankPolicy`] refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankError {
+    pub bank: u8,
+    pub count: usize,
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bank {} is out of range, the preset has {} banks",
+            self.bank, self.count
+        )
+    }
+}
+
+impl Error for BankError {}
+
+/// The banks a binding applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Banks {
+    One(u7),
+    /// Every bank, for global controls such as a master fader.
+    All,
+}
+
+impl fmt::Display for Banks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Banks::One(bank) => write!(f, "bank {}", bank),
+            Banks::All => write!(f, "all banks"),
+        }
+    }
+}
+
+/// A binding to a bank, layer or event the preset doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindError {
+    Bank(BankError),
+    Layer {
+        layer: u8,
+        count: usize,
+    },
+    /// A binding to an address the preset doesn't declare.
+    Unknown(Address),
+    /// A button event bound to a component that isn't a button.
+    Event {
+        address: Address,
+        event: ButtonEvent,
+    },
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindError::Bank(err) => err.fmt(f),
+            BindError::Layer { layer, count } => write!(
+                f,
+                "layer {} is out of range, the preset has {} modifiers",
+                layer,
+                count - 1
+            ),
+            BindError::Unknown(address) => write!(f, "{} is not in the preset", address),
+            BindError::Event { address, event } => write!(
+                f,
+                "{} is not a button, only buttons have {} events",
+                address, event
+            ),
+        }
+    }
+}
+
+impl Error for BindError {}
+
+/// A button that switches to its layer while held.
+#[derive(Debug, Clone, Copy)]
+struct Modifier {
+    /// The modifier's channel, or else the preset's. `None` for any channel.
+    channel: Option<u4>,
+    address: Address,
+    held: bool,
+}
+
+/// Bindings are kept per layer for every bank, and per page for single
+/// banks, one page for each layer of each bank: page `bank * layers + layer`.
 #[derive(Clone)]
 pub struct Controller {
     bank: u7,
-    controllers: HashMap<u7, ComponentType>,
-    sliders: Vec<Vec<Slider>>,
-    buttons: Vec<Vec<Button>>,
-    dials: Vec<Vec<Dial>>,
+    /// The active layer, 0 unless a modifier is held.
+    layer: u8,
+    layers: usize,
+    /// Modifier buttons, modifier `i` switching to layer `i + 1`.
+    modifiers: Vec<Modifier>,
+    /// The lowest value that counts as a press, for modifiers.
+    threshold: u8,
+    bank_names: Vec<String>,
+    bank_policy: BankPolicy,
+    bank_triggers: Vec<BankTrigger>,
+    /// Where every component is, the same for every page.
+    layout: Layout,
+    /// The last MSB received by each 14-bit component, by slot, which later
+    /// LSBs refine.
+    msbs: Vec<Option<u7>>,
+    /// Per layer, the components bound on every bank, by slot.
+    shared: Vec<HashMap<usize, Component>>,
+    /// Per page, the components bound on that page's bank alone, by slot,
+    /// which take the place of shared ones.
+    pages: Vec<HashMap<usize, Component>>,
+    /// Assembles NRPN sequences, when the preset has NRPN components.
+    parameters: Option<ParameterParser>,
+    /// Feedback declared in the preset, by component.
+    feedback: HashMap<(Option<u4>, Address), Feedback>,
+    /// Per bank, the callbacks that send each bound component's state.
+    syncs: Vec<Syncs>,
+    sink: Option<SharedSink>,
+    /// Per bank, the last value received by each component, by slot, on
+    /// whichever layer was active. Values are decoded: endless encoders hold
+    /// the sum of the steps they sent, as a relative value.
+    values: Vec<Vec<Option<Value>>>,
 }
 
 impl Controller {
-    // pub fn new() -> Self {
-    //     let mut controllers = HashMap::new();
-    //
-    //     // create sliders with controller numbers 3-11, inclusive
-    //     let slider_controllers = vec![].into_iter().chain(3..=11).collect::<Vec<_>>();
-    //     slider_controllers.iter().for_each(|i| {
-    //         controllers.insert(u7::from(*i), ComponentType::Slider);
-    //     });
-    //     let sliders = slider_controllers
-    //         .into_iter()
-    //         .map(|i| Slider::new(u7::from(i)))
-    //         .collect::<Vec<_>>();
-    //
-    //     // 1, 2, 23-31, 44-49, 64, 67
-    //     let button_controllers = vec![1, 2]
-    //         .into_iter()
-    //         .chain(23..=31)
-    //         .chain(44..=49)
-    //         .chain(vec![64, 67].into_iter())
-    //         .collect::<Vec<_>>();
-    //     button_controllers.iter().for_each(|i| {
-    //         controllers.insert(u7::from(*i), ComponentType::Button);
-    //     });
-    //     let buttons = button_controllers
-    //         .into_iter()
-    //         .map(|i| Button::new(u7::from(i)))
-    //         .collect::<Vec<_>>();
-    //
-    //     // 12-21 inclusive
-    //     let dial_controllers = vec![].into_iter().chain(12..=22).collect::<Vec<_>>();
-    //     dial_controllers.iter().for_each(|i| {
-    //         controllers.insert(u7::from(*i), ComponentType::Dial);
-    //     });
-    //     let dials = dial_controllers
-    //         .into_iter()
-    //         .map(|i| Dial::new(u7::from(i)))
-    //         .collect::<Vec<_>>();
-    //
-    //     Controller {
-    //         bank: u7::from(0),
-    //         controllers,
-    //         sliders: (0..=8).map(|_| sliders.clone()).collect(),
-    //         buttons: (0..=8).map(|_| buttons.clone()).collect(),
-    //         dials: (0..=8).map(|_| dials.clone()).collect(),
-    //     }
-    // }
-    pub fn from_preset(preset: Preset) -> Self {
-        let sliders = Self::sliders_from_preset(preset.components.sliders);
-        let buttons = Self::buttons_from_preset(preset.components.buttons);
-        let dials = Self::dials_from_preset(preset.components.dials);
-        let mut controllers = HashMap::new();
-        sliders.iter().for_each(|s| {
-            controllers.insert(s.get_controller(), ComponentType::Slider);
-        });
-        buttons.iter().for_each(|b| {
-            controllers.insert(b.get_controller(), ComponentType::Button);
-        });
-        dials.iter().for_each(|d| {
-            controllers.insert(d.get_controller(), ComponentType::Dial);
-        });
-        Self {
-            bank: u7::from(0),
-            controllers,
-            sliders: (0..=8).map(|_| sliders.clone()).collect(),
-            buttons: (0..=8).map(|_| buttons.clone()).collect(),
-            dials: (0..=8).map(|_| dials.clone()).collect(),
-        }
-    }
-    fn sliders_from_preset(components: String) -> Vec<Slider> {
-        let controler_ids = components
-            .split(",")
-            .map(|s| {
-                if s.len() == 1 {
-                    vec![s.parse::<u8>().unwrap()]
-                } else {
-                    let mut split = s.split("-");
-                    let start = split.next().unwrap().parse::<u8>().unwrap();
-                    let end = split.next().unwrap().parse::<u8>().unwrap();
-                    (start..=end).collect::<Vec<_>>()
-                }
-            })
-            .flatten()
-            .collect::<Vec<_>>();
-        controler_ids
+    pub fn from_preset(preset: Preset) -> Result<Self, PresetError> {
+        let layout = Layout::new(preset.layout()?, &preset.components.high_resolution()?);
+        let parameters = preset
+            .components
+            .nrpn
+            .as_ref()
+            .map(|nrpn| ParameterParser::new(nrpn.fine));
+        let default_channel = preset
+            .channel
+            .and_then(|c| c.checked_sub(1))
+            .and_then(u4::try_from);
+        let feedback = preset
+            .feedback
             .iter()
-            .map(|i| u7::from(*i))
-            .map(|i| Slider::new(i))
-            .collect::<Vec<_>>()
-    }
-    fn buttons_from_preset(components: String) -> Vec<Button> {
-        let controler_ids = components
-            .split(",")
-            .map(|s| {
-                if s.contains("-") {
-                    let mut split = s.split("-");
-                    let start = split.next().unwrap().parse::<u8>().unwrap();
-                    let end = split.next().unwrap().parse::<u8>().unwrap();
-                    (start..=end).collect::<Vec<_>>()
-                } else {
-                    vec![s.parse::<u8>().unwrap()]
-                }
+            .filter_map(|entry| {
+                let channel = entry.component.channel().ok()?;
+                let address = entry.component.address().ok()?;
+                let output = channel.or(default_channel).unwrap_or(u4::from(0));
+                Some(((channel, address), Feedback::new(entry, output)?))
             })
-            .flatten()
-            .collect::<Vec<_>>();
-        controler_ids
+            .collect();
+        let modifiers = preset
+            .modifiers
             .iter()
-            .map(|i| u7::from(*i))
-            .map(|i| Button::new(i))
-            .collect::<Vec<_>>()
-    }
-    fn dials_from_preset(components: String) -> Vec<Dial> {
-        let controler_ids = components
-            .split(",")
-            .map(|s| {
-                if s.len() == 1 {
-                    vec![s.parse::<u8>().unwrap()]
-                } else {
-                    let mut split = s.split("-");
-                    let start = split.next().unwrap().parse::<u8>().unwrap();
-                    let end = split.next().unwrap().parse::<u8>().unwrap();
-                    (start..=end).collect::<Vec<_>>()
-                }
+            .enumerate()
+            .map(|(i, modifier)| {
+                let error = |error| PresetError::Modifier {
+                    layer: i + 1,
+                    error,
+                };
+                Ok(Modifier {
+                    channel: modifier.channel().map_err(error)?.or(default_channel),
+                    address: modifier.address().map_err(error)?,
+                    held: false,
+                })
             })
-            .flatten()
-            .collect::<Vec<_>>();
-        controler_ids
-            .iter()
-            .map(|i| u7::from(*i))
-            .map(|i| Dial::new(i))
-            .collect::<Vec<_>>()
+            .collect::<Result<Vec<_>, _>>()?;
+        let layers = modifiers.len() + 1;
+        let banks = preset.banks.clamp(1, 128) as usize;
+        let pages = banks * layers;
+        Ok(Self {
+            bank: u7::from(0),
+            layer: 0,
+            layers,
+            modifiers,
+            threshold: preset.button_behavior.threshold,
+            bank_names: preset.bank_names,
+            bank_policy: preset.bank_policy,
+            bank_triggers: preset.bank_triggers,
+            msbs: vec![None; layout.len()],
+            shared: vec![HashMap::new(); layers],
+            pages: vec![HashMap::new(); pages],
+            values: vec![vec![None; layout.len()]; banks],
+            layout,
+            parameters,
+            feedback,
+            syncs: (0..pages).map(|_| HashMap::new()).collect(),
+            sink: None,
+        })
+    }
+}
+
+// Getters
+impl Controller {
+    pub fn bank(&self) -> u7 {
+        self.bank
+    }
+    pub fn bank_count(&self) -> usize {
+        self.pages.len() / self.layers
+    }
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+    /// The number of layers, the base layer plus one per modifier.
+    pub fn layer_count(&self) -> usize {
+        self.layers
+    }
+    /// The index of the active bank and layer's components.
+    fn page(&self) -> usize {
+        self.bank.as_int() as usize * self.layers + self.layer as usize
+    }
+    /// The indices of `layer` in `banks`.
+    fn pages(&self, banks: Banks, layer: u8) -> Result<Vec<usize>, BindError> {
+        let count = self.bank_count();
+        if layer as usize >= self.layers {
+            return Err(BindError::Layer {
+                layer,
+                count: self.layers,
+            });
+        }
+        let banks = match banks {
+            Banks::One(bank) if bank.as_int() as usize >= count => {
+                return Err(BindError::Bank(BankError {
+                    bank: bank.as_int(),
+                    count,
+                }))
+            }
+            Banks::One(bank) => bank.as_int() as usize..bank.as_int() as usize + 1,
+            Banks::All => 0..count,
+        };
+        Ok(banks
+            .map(|bank| bank * self.layers + layer as usize)
+            .collect())
+    }
+    /// Whether `cc` is a bank trigger rather than a component.
+    pub fn claims_cc(&self, cc: u7) -> bool {
+        self.bank_triggers.iter().any(|t| t.claims_cc(cc))
+    }
+    /// Whether `note` is a bank trigger rather than a component.
+    pub fn claims_note(&self, note: u7) -> bool {
+        self.bank_triggers.iter().any(|t| t.claims_note(note))
+    }
+    /// The last value received by the CC component on `cc` in `bank`,
+    /// preferring one listening on every channel. 14-bit components report
+    /// their combined value under their MSB.
+    pub fn value(&self, bank: u7, cc: u7) -> Option<Value> {
+        let values = self.values.get(bank.as_int() as usize)?;
+        let mut slots = self.layout.matching(None, Address::Cc(cc));
+        slots.sort_by_key(|slot| self.layout.component(*slot).channel().is_some());
+        slots.into_iter().find_map(|slot| values[slot])
+    }
+    /// The last value received by the component at `address` on `channel` in
+    /// `bank`, or `None` if it hasn't sent anything in that bank yet.
+    pub fn component_value(
+        &self,
+        bank: u7,
+        channel: Option<u4>,
+        address: Address,
+    ) -> Option<Value> {
+        self.values.get(bank.as_int() as usize)?[self.layout.slot(channel, address)?]
+    }
+    /// The component a message at `address` on `channel` is routed to, and
+    /// the last value it received in the active bank. The LSB of a 14-bit
+    /// CC reports the component on its MSB.
+    pub fn received(&self, channel: u4, address: Address) -> Option<(Address, Value)> {
+        let slot = match address {
+            Address::Cc(cc) => match self.layout.cc(channel, cc) {
+                Route::Unassigned => None,
+                Route::Component(slot) | Route::Msb(slot) | Route::Lsb(slot) => Some(slot),
+            },
+            Address::Note(note) => self.layout.note(channel, note),
+            Address::PitchBend(channel) => self.layout.pitch_bend(channel),
+            Address::Nrpn(_) => self.layout.find(channel, address),
+        }?;
+        let value = self.values[self.bank.as_int() as usize][slot]?;
+        Some((self.layout.component(slot).address(), value))
+    }
+    /// Every value received in `bank`, by component.
+    pub fn values(&self, bank: u7) -> impl Iterator<Item = (Option<u4>, Address, Value)> + '_ {
+        self.values
+            .get(bank.as_int() as usize)
+            .into_iter()
+            .flat_map(|values| values.iter().enumerate())
+            .filter_map(|(slot, value)| {
+                let component = self.layout.component(slot);
+                Some((component.channel(), component.address(), (*value)?))
+            })
+    }
+    pub fn bank_name(&self, bank: u7) -> Option<&str> {
+        self.bank_names
+            .get(bank.as_int() as usize)
+            .map(String::as_str)
+    }
+}
+
+// Feedback
+impl Controller {
+    /// Where feedback is sent. Without a sink, feedback is dropped.
+    pub fn set_sink(&mut self, sink: SharedSink) {
+        self.sink = Some(sink);
+    }
+    pub fn sink(&self) -> Option<&SharedSink> {
+        self.sink.as_ref()
+    }
+    /// The key of the preset's feedback for a component, preferring one
+    /// declared on `channel` over one for any channel.
+    fn feedback_key(&self, channel: Option<u4>, address: Address) -> Option<(Option<u4>, Address)> {
+        [(channel, address), (None, address)]
+            .into_iter()
+            .find(|key| self.feedback.contains_key(key))
+    }
+    pub fn feedback(&self, channel: Option<u4>, address: Address) -> Option<Feedback> {
+        let key = self.feedback_key(channel, address)?;
+        self.feedback.get(&key).copied()
+    }
+    /// Registers how the component bound at `channel` and `address` on
+    /// `layer` of `banks` reports its state, for [`Controller::sync`]. Does
+    /// nothing if the preset has no feedback for it.
+    pub fn bind_feedback(
+        &mut self,
+        banks: Banks,
+        layer: u8,
+        channel: Option<u4>,
+        address: Address,
+        callback: Box<dyn FeedbackCallback>,
+    ) -> Result<(), BindError> {
+        let pages = self.pages(banks, layer)?;
+        if let Some(key) = self.feedback_key(channel, address) {
+            for page in pages {
+                self.syncs[page].insert(key, callback.clone());
+            }
+        }
+        Ok(())
+    }
+    /// Re-sends the whole surface for the active bank: the state of every
+    /// bound component, and "off" for components with feedback but no
+    /// binding in this bank.
+    pub fn sync(&self) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let syncs = &self.syncs[self.page()];
+        for (key, feedback) in &self.feedback {
+            let modifier = self
+                .modifiers
+                .iter()
+                .find(|m| self.feedback_key(m.channel, m.address) == Some(*key));
+            match (modifier, syncs.get(key)) {
+                (Some(modifier), _) => feedback.send(sink, FeedbackState::Switch(modifier.held)),
+                (None, Some(sync)) => sync(),
+                (None, None) => feedback.send(sink, FeedbackState::Switch(false)),
+            }
+        }
     }
 }
 
 // Setters
 impl Controller {
-    pub fn set_bank(&mut self, bank: u7) {
-        self.bank = bank;
+    /// Switches to `bank`, applying the preset's [`BankPolicy`] if it is out of
+    /// range. Returns the bank that is now active.
+    pub fn set_bank(&mut self, bank: u7) -> Result<u7, BankError> {
+        self.select_bank(bank.as_int())
     }
-    fn set_slider(&mut self, controller: u7, value: u7) {
-        self.sliders[self.bank.as_int() as usize]
-            .iter_mut()
-            .find(|s| s.get_controller() == controller)
-            .map(|s| s.set_value(value));
+    fn select_bank(&mut self, bank: u8) -> Result<u7, BankError> {
+        let count = self.bank_count();
+        if bank as usize >= count {
+            match self.bank_policy {
+                BankPolicy::Reject => return Err(BankError { bank, count }),
+                BankPolicy::Clamp => self.bank = u7::from((count - 1) as u8),
+            }
+        } else {
+            self.bank = u7::from(bank);
+        }
+        self.release_page();
+        Ok(self.bank)
     }
-    fn bind_slider(&mut self, controller: u7, callback: Box<dyn ComponentCallback>) {
-        self.sliders[self.bank.as_int() as usize]
-            .iter_mut()
-            .find(|s| s.get_controller() == controller)
-            .map(|s| s.set_callback(callback));
+    /// Switches to `layer`, as the modifiers are pressed and released.
+    fn set_layer(&mut self, layer: u8) {
+        if (layer as usize) < self.layers {
+            self.layer = layer;
+            self.release_page();
+        }
     }
-    fn set_button(&mut self, controller: u7, value: u7) {
-        self.buttons[self.bank.as_int() as usize]
-            .iter_mut()
-            .find(|b| b.get_controller() == controller)
-            .map(|b| b.set_value(value));
+    /// Forgets the fader positions of the active page, which may have moved
+    /// while another bank or layer was active, and fetches the values of
+    /// their targets for soft takeover.
+    fn release_page(&mut self) {
+        let page = self.page();
+        self.pages[page]
+            .values_mut()
+            .chain(self.shared[self.layer as usize].values_mut())
+            .for_each(Component::release);
     }
-    fn bind_button(&mut self, controller: u7, callback: Box<dyn ComponentCallback>) {
-        self.buttons[self.bank.as_int() as usize]
+    /// Tracks a modifier press or release and switches to the layer of the
+    /// first modifier held, or the base layer when none are. Returns `false`
+    /// if `address` on `channel` isn't a modifier.
+    fn set_modifier(&mut self, channel: u4, address: Address, value: u7) -> bool {
+        let Some(modifier) = self
+            .modifiers
             .iter_mut()
-            .find(|b| b.get_controller() == controller)
-            .map(|b| b.set_callback(callback));
+            .find(|m| m.address == address && m.channel.is_none_or(|c| c == channel))
+        else {
+            return false;
+        };
+        modifier.held = value.as_int() >= self.threshold;
+        let modifier = *modifier;
+        let layer = self
+            .modifiers
+            .iter()
+            .position(|m| m.held)
+            .map_or(0, |i| i as u8 + 1);
+        if layer != self.layer {
+            self.set_layer(layer);
+            self.sync();
+        } else if let (Some(sink), Some(feedback)) =
+            (&self.sink, self.feedback(modifier.channel, address))
+        {
+            feedback.send(sink, FeedbackState::Switch(modifier.held));
+        }
+        true
     }
-    fn set_dial(&mut self, controller: u7, value: u7) {
-        self.dials[self.bank.as_int() as usize]
-            .iter_mut()
-            .find(|d| d.get_controller() == controller)
-            .map(|d| d.set_value(value));
+    /// Switches bank if `input` matches one of the preset's bank triggers.
+    /// Returns `None` when nothing matched, leaving the active bank as it was.
+    pub fn trigger_bank(&mut self, input: BankInput) -> Option<Result<u7, BankError>> {
+        let action = self.bank_triggers.iter().find_map(|t| t.matches(input))?;
+        let count = self.bank_count() as u8;
+        let bank = match action {
+            BankAction::Next => (self.bank.as_int() + 1) % count,
+            BankAction::Previous => (self.bank.as_int() + count - 1) % count,
+            BankAction::Bank(bank) => bank,
+        };
+        Some(self.select_bank(bank))
     }
-    fn bind_dial(&mut self, controller: u7, callback: Box<dyn ComponentCallback>) {
-        self.dials[self.bank.as_int() as usize]
-            .iter_mut()
-            .find(|d| d.get_controller() == controller)
-            .map(|d| d.set_callback(callback));
-    }
-    pub fn set_component(&mut self, controller: u7, value: u7) {
-        if let Some(c) = self.controllers.get(&controller) {
-            match c {
-                ComponentType::Slider => self.set_slider(controller, value),
-                ComponentType::Button => self.set_button(controller, value),
-                ComponentType::Dial => self.set_dial(controller, value),
+    /// The bound component in `slot` on the active page, preferring one
+    /// bound on the active bank alone over one bound on every bank.
+    fn component_mut(&mut self, slot: usize) -> Option<&mut Component> {
+        let page = self.page();
+        if self.pages[page].contains_key(&slot) {
+            return self.pages[page].get_mut(&slot);
+        }
+        self.shared[self.layer as usize].get_mut(&slot)
+    }
+    /// Delivers `value` to the component in `slot` in the active page, and
+    /// remembers it as the component's last value in the active bank.
+    fn dispatch(&mut self, slot: usize, value: Value, timestamp: u64) {
+        let stored = &mut self.values[self.bank.as_int() as usize][slot];
+        *stored = match (self.layout.component(slot).decode(value), *stored) {
+            (Value::Relative(step), Some(Value::Relative(total))) => {
+                Some(Value::Relative(total.saturating_add(step)))
+            }
+            (decoded, _) => Some(decoded),
+        };
+        if let Some(component) = self.component_mut(slot) {
+            component.set_value_at(value, timestamp);
+        }
+    }
+    /// Routes a CC to its component. For 14-bit components an MSB is
+    /// delivered straight away with an LSB of 0, as the MIDI spec has it, and
+    /// refined when its LSB (CC + 32) arrives. An LSB without any MSB before
+    /// it is dropped. `timestamp` is when the message was received, in
+    /// microseconds, and drives encoder acceleration.
+    pub fn set_component(&mut self, channel: u4, controller: u7, value: u7, timestamp: u64) {
+        if self.set_modifier(channel, Address::Cc(controller), value) {
+            return;
+        }
+        if let Some(parameters) = &mut self.parameters {
+            match parameters.feed(channel, controller, value) {
+                ParameterInput::NotParameter => (),
+                ParameterInput::Pending => return,
+                ParameterInput::Complete(parameter) => {
+                    return self.set_parameter(channel, parameter, timestamp)
+                }
             }
         }
+        match self.layout.cc(channel, controller) {
+            Route::Unassigned => (),
+            Route::Component(slot) => self.dispatch(slot, Value::U7(value), timestamp),
+            Route::Msb(slot) => {
+                self.msbs[slot] = Some(value);
+                let value = u14::from((value.as_int() as u16) << 7);
+                self.dispatch(slot, Value::U14(value), timestamp);
+            }
+            Route::Lsb(slot) => {
+                let Some(high) = self.msbs[slot] else {
+                    return;
+                };
+                let value = u14::from(((high.as_int() as u16) << 7) | value.as_int() as u16);
+                self.dispatch(slot, Value::U14(value), timestamp);
+            }
+        }
+    }
+    /// Routes a Note On (or Note Off, as velocity 0) to the component on
+    /// `note`.
+    pub fn set_note(&mut self, channel: u4, note: u7, velocity: u7) {
+        if self.set_modifier(channel, Address::Note(note), velocity) {
+            return;
+        }
+        if let Some(slot) = self.layout.note(channel, note) {
+            self.dispatch(slot, Value::U7(velocity), 0);
+        }
+    }
+    /// Routes a complete NRPN to its component. RPNs have no components and
+    /// are dropped.
+    fn set_parameter(&mut self, channel: u4, parameter: Parameter, timestamp: u64) {
+        if parameter.kind != ParameterKind::Nrpn {
+            return;
+        }
+        if let Some(slot) = self.layout.find(channel, Address::Nrpn(parameter.number)) {
+            self.dispatch(slot, parameter.value, timestamp);
+        }
+    }
+    /// Routes pitch bend on `channel` to its fader, as a 14-bit value.
+    pub fn set_pitch_bend(&mut self, channel: u4, bend: u14) {
+        if let Some(slot) = self.layout.pitch_bend(channel) {
+            self.dispatch(slot, Value::U14(bend), 0);
+        }
+    }
+    /// Sets where the components at `address` read their target's value
+    /// from, for soft takeover, on `layer` of `banks`.
+    pub fn bind_source(
+        &mut self,
+        banks: Banks,
+        layer: u8,
+        channel: Option<u4>,
+        address: Address,
+        source: Box<dyn ValueSource>,
+    ) -> Result<(), BindError> {
+        let matching = self.layout.matching(channel, address);
+        self.bind_slots(banks, layer, &matching, |component| {
+            component.set_source(source.clone())
+        })
+    }
+    /// Binds the components at `address` on `layer` of `banks`, whichever
+    /// bank is active.
+    pub fn bind(
+        &mut self,
+        banks: Banks,
+        layer: u8,
+        channel: Option<u4>,
+        address: Address,
+        callback: Box<dyn ComponentCallback>,
+    ) -> Result<(), BindError> {
+        self.bind_event(banks, layer, channel, address, ButtonEvent::Press, callback)
+    }
+    /// Binds `event` of the buttons at `address`. Presses bind like
+    /// [`Controller::bind`], any other event only applies to buttons.
+    pub fn bind_event(
+        &mut self,
+        banks: Banks,
+        layer: u8,
+        channel: Option<u4>,
+        address: Address,
+        event: ButtonEvent,
+        callback: Box<dyn ComponentCallback>,
+    ) -> Result<(), BindError> {
+        let matching = self.layout.matching(channel, address);
+        if matching.is_empty() {
+            return Err(BindError::Unknown(address));
+        }
+        if matching
+            .iter()
+            .any(|slot| !self.layout.component(*slot).has_event(event))
+        {
+            return Err(BindError::Event { address, event });
+        }
+        self.bind_slots(banks, layer, &matching, |component| {
+            component.set_callback(event, callback.clone())
+        })
     }
-    pub fn bind_component(&mut self, controller: u7, callback: Box<dyn ComponentCallback>) {
-        if let Some(c) = self.controllers.get(&controller) {
-            match c {
-                ComponentType::Slider => self.bind_slider(controller, callback),
-                ComponentType::Button => self.bind_button(controller, callback),
-                ComponentType::Dial => self.bind_dial(controller, callback),
+    /// Applies `bind` to the components in `slots` on `layer` of `banks`,
+    /// copying each one from the layout when it is first bound. A binding on
+    /// every bank is kept once, and also applies to components bound on a
+    /// single bank, which start out as copies of the shared ones.
+    fn bind_slots(
+        &mut self,
+        banks: Banks,
+        layer: u8,
+        slots: &[usize],
+        bind: impl Fn(&mut Component),
+    ) -> Result<(), BindError> {
+        let pages = self.pages(banks, layer)?;
+        let shared = &mut self.shared[layer as usize];
+        for &slot in slots {
+            if banks == Banks::All {
+                let component = shared
+                    .entry(slot)
+                    .or_insert_with(|| self.layout.component(slot).clone());
+                bind(component);
+            }
+            for &page in &pages {
+                let component = match self.pages[page].entry(slot) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    // the page has nothing of its own to shadow the binding
+                    Entry::Vacant(_) if banks == Banks::All => continue,
+                    Entry::Vacant(entry) => {
+                        let template = shared.get(&slot);
+                        entry.insert(template.unwrap_or(self.layout.component(slot)).clone())
+                    }
+                };
+                bind(component);
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::midi::fixtures;
+
+    /// Binds `address` on every bank and collects the values it receives.
+    fn record(controller: &mut Controller, address: Address) -> Arc<Mutex<Vec<Value>>> {
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let sink = values.clone();
+        let callback = move |value: Value| sink.lock().unwrap().push(value);
+        controller
+            .bind(Banks::All, 0, None, address, Box::new(callback))
+            .unwrap();
+        values
+    }
+
+    #[test]
+    fn high_resolution_msb_and_lsb() {
+        let mut controller = fixtures::controller(
+            r#"
+components:
+  high_resolution: "3"
+"#,
+        );
+        let values = record(&mut controller, Address::Cc(u7::from(3)));
+        let channel = u4::from(0);
+        let u14 = |value: u16| Value::U14(u14::from(value));
+        // an LSB before any MSB has nothing to refine
+        controller.set_component(channel, u7::from(35), u7::from(5), 0);
+        assert_eq!(*values.lock().unwrap(), []);
+        // MSB only, as coarse controllers send
+        controller.set_component(channel, u7::from(3), u7::from(64), 0);
+        assert_eq!(*values.lock().unwrap(), [u14(64 << 7)]);
+        // MSB then LSB
+        controller.set_component(channel, u7::from(3), u7::from(65), 0);
+        controller.set_component(channel, u7::from(35), u7::from(10), 0);
+        assert_eq!(
+            values.lock().unwrap()[1..],
+            [u14(65 << 7), u14((65 << 7) | 10)]
+        );
+        // LSB only, refining the last MSB
+        controller.set_component(channel, u7::from(35), u7::from(20), 0);
+        assert_eq!(values.lock().unwrap()[3..], [u14((65 << 7) | 20)]);
+    }
+
+    #[test]
+    fn pitch_bend_reaches_its_channel_only() {
+        let mut controller = fixtures::controller(
+            r#"
+components:
+  pitchbend: "1-3"
+"#,
+        );
+        let first = record(&mut controller, Address::PitchBend(u4::from(0)));
+        let second = record(&mut controller, Address::PitchBend(u4::from(1)));
+        controller.set_pitch_bend(u4::from(1), u14::from(12000));
+        // channel 4 has no fader
+        controller.set_pitch_bend(u4::from(3), u14::from(500));
+        assert_eq!(*first.lock().unwrap(), []);
+        assert_eq!(*second.lock().unwrap(), [Value::U14(u14::from(12000))]);
+    }
+
+    #[test]
+    fn button_events_only_bind_to_buttons() {
+        let mut controller = fixtures::controller(
+            r#"
+components:
+  pads: "36-39"
+"#,
+        );
+        let pad = Address::Note(u7::from(36));
+        let callback = Box::new(|_: Value| ());
+        assert_eq!(
+            controller.bind_event(
+                Banks::All,
+                0,
+                None,
+                pad,
+                ButtonEvent::LongPress,
+                callback.clone()
+            ),
+            Err(BindError::Event {
+                address: pad,
+                event: ButtonEvent::LongPress
+            })
+        );
+        assert_eq!(
+            controller.bind_event(
+                Banks::All,
+                0,
+                None,
+                pad,
+                ButtonEvent::Press,
+                callback.clone()
+            ),
+            Ok(())
+        );
+        let button = Address::Cc(u7::from(23));
+        assert_eq!(
+            controller.bind_event(
+                Banks::All,
+                0,
+                None,
+                button,
+                ButtonEvent::LongPress,
+                callback
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn banks_past_the_last_follow_the_policy() {
+        for (policy, expected) in [
+            ("reject", Err(BankError { bank: 6, count: 4 })),
+            ("clamp", Ok(u7::from(3))),
+        ] {
+            let mut controller = fixtures::controller(&format!("banks: 4\nbank_policy: {policy}"));
+            controller.set_bank(u7::from(1)).unwrap();
+            assert_eq!(controller.set_bank(u7::from(6)), expected, "{policy}");
+            let active = expected.unwrap_or(u7::from(1));
+            assert_eq!(controller.bank(), active, "{policy}");
+        }
+    }
+
+    #[test]
+    fn single_bank_bindings_shadow_shared_ones() {
+        let mut controller = fixtures::controller(
+            r#"
+banks: 3
+"#,
+        );
+        let fader = Address::Cc(u7::from(3));
+        let shared = record(&mut controller, fader);
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let sink = values.clone();
+        let callback = move |value: Value| sink.lock().unwrap().push(value);
+        controller
+            .bind(Banks::One(u7::from(1)), 0, None, fader, Box::new(callback))
+            .unwrap();
+        let channel = u4::from(0);
+        for bank in 0..3 {
+            controller.set_bank(u7::from(bank)).unwrap();
+            controller.set_component(channel, u7::from(3), u7::from(bank), 0);
+        }
+        let at = |value: u8| Value::U7(u7::from(value));
+        assert_eq!(*shared.lock().unwrap(), [at(0), at(2)]);
+        assert_eq!(*values.lock().unwrap(), [at(1)]);
+    }
+
+    #[test]
+    fn unknown_components_are_refused() {
+        let mut controller = fixtures::controller("");
+        let address = Address::Cc(u7::from(60));
+        assert_eq!(
+            controller.bind(Banks::All, 0, None, address, Box::new(|_: Value| ())),
+            Err(BindError::Unknown(address))
+        );
+    }
+
+    #[test]
+    fn modifiers_switch_layers_while_held() {
+        let mut controller = fixtures::controller(
+            r#"
+channel: 2
+modifiers:
+  - { cc: 23 }
+"#,
+        );
+        let fader = Address::Cc(u7::from(3));
+        let record_layer = |controller: &mut Controller, layer| {
+            let values = Arc::new(Mutex::new(Vec::new()));
+            let sink = values.clone();
+            let callback = move |value: Value| sink.lock().unwrap().push(value);
+            controller
+                .bind(Banks::All, layer, None, fader, Box::new(callback))
+                .unwrap();
+            values
+        };
+        let base = record_layer(&mut controller, 0);
+        let shifted = record_layer(&mut controller, 1);
+        let channel = u4::from(1);
+        let move_fader = |controller: &mut Controller, value| {
+            controller.set_component(channel, u7::from(3), u7::from(value), 0)
+        };
+
+        controller.set_component(channel, u7::from(23), u7::from(127), 0);
+        assert_eq!(controller.layer(), 1);
+        move_fader(&mut controller, 10);
+        controller.set_component(channel, u7::from(23), u7::from(0), 0);
+        assert_eq!(controller.layer(), 0);
+        move_fader(&mut controller, 20);
+        assert_eq!(*shifted.lock().unwrap(), [Value::U7(u7::from(10))]);
+        assert_eq!(*base.lock().unwrap(), [Value::U7(u7::from(20))]);
+
+        // the modifier listens on the preset's channel only
+        controller.set_component(u4::from(0), u7::from(23), u7::from(127), 0);
+        assert_eq!(controller.layer(), 0);
+    }
+
+    #[test]
+    fn invalid_modifiers_are_refused() {
+        let preset = serde_yaml::from_str(
+            r#"
+components:
+  buttons: "23-31"
+modifiers:
+  - { cc: 23 }
+  - { cc: 24, note: 24 }
+"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Controller::from_preset(preset),
+            Err(PresetError::Modifier { layer: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn values_are_stored_decoded() {
+        let mut controller = fixtures::controller(
+            r#"
+components:
+  high_resolution: "3"
+  encoders:
+    binary_offset: "12"
+"#,
+        );
+        let channel = u4::from(0);
+        for step in [65, 66, 63] {
+            controller.set_component(channel, u7::from(12), u7::from(step), 0);
+        }
+        assert_eq!(
+            controller.received(channel, Address::Cc(u7::from(12))),
+            Some((Address::Cc(u7::from(12)), Value::Relative(2)))
+        );
+        controller.set_component(channel, u7::from(13), u7::from(65), 0);
+        assert_eq!(
+            controller.received(channel, Address::Cc(u7::from(13))),
+            Some((Address::Cc(u7::from(13)), Value::U7(u7::from(65))))
+        );
+        controller.set_component(channel, u7::from(3), u7::from(1), 0);
+        controller.set_component(channel, u7::from(35), u7::from(2), 0);
+        let combined = Some((Address::Cc(u7::from(3)), Value::U14(u14::from(130))));
+        assert_eq!(
+            controller.received(channel, Address::Cc(u7::from(35))),
+            combined
+        );
+        assert_eq!(
+            controller.received(channel, Address::Cc(u7::from(3))),
+            combined
+        );
+        assert_eq!(controller.received(channel, Address::Cc(u7::from(4))), None);
     }
 }
@@ -0,0 +1,181 @@
+use std::sync::{Arc, Mutex};
+
+use midir::MidiOutputConnection;
+use midly::live::LiveEvent;
+use midly::num::{u14, u4, u7};
+use midly::{MidiMessage, PitchBend};
+use serde::Deserialize;
+
+use crate::midi::components::ComponentRef;
+
+/// Somewhere feedback messages can be sent, normally the controller's MIDI
+/// output port.
+pub trait MidiSink: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), String>;
+}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), String> {
+        MidiOutputConnection::send(self, message).map_err(|err| err.to_string())
+    }
+}
+
+pub type SharedSink = Arc<Mutex<Box<dyn MidiSink>>>;
+
+/// The message a component's feedback is sent as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedbackOutput {
+    /// A CC, for LEDs and LED rings.
+    Cc(u8),
+    /// A Note On, for pad and button LEDs.
+    Note(u8),
+    /// Pitch bend on a channel (1-16), for Mackie-style motor faders.
+    PitchBend(u8),
+}
+
+/// Feedback for one component, as declared in a preset.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedbackEntry {
+    #[serde(flatten)]
+    pub component: ComponentRef,
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub send: FeedbackOutput,
+    /// The value sent for "on", e.g. a muted session.
+    #[serde(default = "default_on")]
+    pub on: u8,
+    #[serde(default)]
+    pub off: u8,
+}
+
+fn default_on() -> u8 {
+    127
+}
+
+impl FeedbackEntry {
+    /// Problems with the entry, for [`Preset::validate`].
+    ///
+    /// [`Preset::validate`]: crate::midi::Preset::validate
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(err) = self.component.channel() {
+            problems.push(err);
+        }
+        if let Err(err) = self.component.address() {
+            problems.push(err);
+        }
+        match self.send {
+            FeedbackOutput::Cc(n) | FeedbackOutput::Note(n) if n > 127 => {
+                problems.push(format!("send: {} is above 127", n))
+            }
+            FeedbackOutput::PitchBend(channel) if !(1..=16).contains(&channel) => {
+                problems.push(format!(
+                    "send: pitch bend channel {} is not between 1 and 16",
+                    channel
+                ))
+            }
+            _ => (),
+        }
+        [("on", self.on), ("off", self.off)]
+            .iter()
+            .filter(|(_, value)| *value > 127)
+            .for_each(|(key, value)| problems.push(format!("{}: {} is above 127", key, value)));
+        problems
+    }
+}
+
+/// What a component should display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedbackState {
+    /// An LED, on or off.
+    Switch(bool),
+    /// A fader or LED ring position, `0.0..=1.0`.
+    Level(f32),
+}
+
+/// A resolved [`FeedbackEntry`], ready to encode states into messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feedback {
+    channel: u4,
+    output: FeedbackOutput,
+    on: u7,
+    off: u7,
+}
+
+impl Feedback {
+    /// Resolves `entry`, sending CCs and notes on `channel`. Returns `None`
+    /// for entries [`FeedbackEntry::problems`] would report.
+    pub fn new(entry: &FeedbackEntry, channel: u4) -> Option<Self> {
+        let channel = match entry.send {
+            FeedbackOutput::PitchBend(channel) => u4::try_from(channel.checked_sub(1)?)?,
+            _ => channel,
+        };
+        Some(Self {
+            channel,
+            output: entry.send,
+            on: u7::try_from(entry.on)?,
+            off: u7::try_from(entry.off)?,
+        })
+    }
+    /// The MIDI message that shows `state`.
+    pub fn encode(&self, state: FeedbackState) -> Vec<u8> {
+        let level = |max: f32| match state {
+            FeedbackState::Switch(on) => {
+                let value = if on { self.on } else { self.off };
+                value.as_int() as f32 / 127.0 * max
+            }
+            FeedbackState::Level(level) => level.clamp(0.0, 1.0) * max,
+        };
+        let message = match self.output {
+            FeedbackOutput::Cc(cc) => MidiMessage::Controller {
+                controller: u7::from(cc),
+                value: u7::from(level(127.0).round() as u8),
+            },
+            FeedbackOutput::Note(note) => MidiMessage::NoteOn {
+                key: u7::from(note),
+                vel: u7::from(level(127.0).round() as u8),
+            },
+            FeedbackOutput::PitchBend(_) => MidiMessage::PitchBend {
+                bend: PitchBend(u14::from(level(16383.0).round() as u16)),
+            },
+        };
+        let mut bytes = Vec::with_capacity(3);
+        LiveEvent::Midi {
+            channel: self.channel,
+            message,
+        }
+        .write_std(&mut bytes)
+        .expect("writing to a Vec can't fail");
+        bytes
+    }
+    pub fn send(&self, sink: &SharedSink, state: FeedbackState) {
+        if let Err(err) = sink.lock().unwrap().send(&self.encode(state)) {
+            eprintln!("ERROR: Couldn't send feedback: {err}");
+        }
+    }
+}
+
+/// Sends the current state of a bound component to the controller.
+pub trait FeedbackCallback: Fn() + Send {
+    fn clone_box<'a>(&self) -> Box<dyn 'a + FeedbackCallback>
+    where
+        Self: 'a;
+}
+
+impl<F> FeedbackCallback for F
+where
+    F: Fn() + Clone + Send,
+{
+    fn clone_box<'a>(&self) -> Box<dyn 'a + FeedbackCallback>
+    where
+        Self: 'a,
+    {
+        Box::new(self.clone())
+    }
+}
+
+impl<'a> Clone for Box<dyn 'a + FeedbackCallback> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
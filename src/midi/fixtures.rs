@@ -0,0 +1,36 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::midi::{Controller, Preset};
+
+/// The components of every test preset, unless it lists its own.
+const COMPONENTS: &str = r#"
+sliders: "3-11"
+buttons: "23-31"
+dials: "12-21"
+"#;
+
+/// A preset read from `source`, with sliders on CC 3-11, buttons on 23-31
+/// and dials on 12-21 added to its `components`.
+pub(crate) fn preset(source: &str) -> Preset {
+    let mut preset: Value = serde_yaml::from_str(source).unwrap();
+    if preset.is_null() {
+        preset = Value::Mapping(Mapping::new());
+    }
+    let components = preset
+        .as_mapping_mut()
+        .unwrap()
+        .entry("components".into())
+        .or_insert_with(|| Value::Mapping(Mapping::new()))
+        .as_mapping_mut()
+        .unwrap();
+    let defaults: Mapping = serde_yaml::from_str(COMPONENTS).unwrap();
+    for (list, numbers) in defaults {
+        components.entry(list).or_insert(numbers);
+    }
+    serde_yaml::from_value(preset).unwrap()
+}
+
+/// A controller built from [`preset`].
+pub(crate) fn controller(source: &str) -> Controller {
+    Controller::from_preset(preset(source)).unwrap()
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use midly::num::{u4, u7};
+
+use crate::midi::ccset::CcSet;
+use crate::midi::components::{Address, Component};
+
+/// Where a CC on a channel goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Unassigned,
+    /// The component in a slot.
+    Component(usize),
+    /// The MSB of the 14-bit component in a slot, delivered straight away
+    /// and kept for its LSB.
+    Msb(usize),
+    /// The LSB of the 14-bit component in a slot, completing its value.
+    Lsb(usize),
+}
+
+/// The components of a preset, in the order of their slots, and tables
+/// resolving every message to a slot. The layout doesn't change after it is
+/// built, so every bank and layer shares its tables. A component is copied
+/// from here when it is first bound, see [`Controller`].
+///
+/// [`Controller`]: crate::midi::Controller
+#[derive(Clone)]
+pub struct Layout {
+    components: Vec<Component>,
+    /// Every component, by channel and address, to its slot.
+    index: HashMap<(Option<u4>, Address), usize>,
+    /// For each channel and CC (`channel * 128 + cc`), where a CC goes. A
+    /// component on the channel takes precedence over one on every channel.
+    ccs: Box<[Route]>,
+    /// For each channel and note, the slot of its component.
+    notes: Box<[Option<usize>]>,
+    /// For each channel, the slot of its pitch-bend fader.
+    pitch_bends: [Option<usize>; 16],
+}
+
+impl Layout {
+    /// Lays out `components`, delivering the CCs in `high_resolution` and
+    /// the CC 32 above them as a single 14-bit value.
+    pub fn new(components: Vec<Component>, high_resolution: &CcSet) -> Self {
+        let index: HashMap<_, _> = components
+            .iter()
+            .enumerate()
+            .map(|(slot, component)| ((component.channel(), component.address()), slot))
+            .collect();
+        let mut ccs = vec![Route::Unassigned; 16 * 128].into_boxed_slice();
+        let mut notes = vec![None; 16 * 128].into_boxed_slice();
+        let mut pitch_bends = [None; 16];
+        // components on every channel first, so those on one channel override them
+        let mut slots: Vec<usize> = (0..components.len()).collect();
+        slots.sort_by_key(|slot| components[*slot].channel().is_some());
+        for slot in slots {
+            let component = &components[slot];
+            let channels = match component.channel() {
+                Some(channel) => channel.as_int() as usize..channel.as_int() as usize + 1,
+                None => 0..16,
+            };
+            for channel in channels {
+                match component.address() {
+                    Address::Cc(cc) if high_resolution.contains(cc) => {
+                        ccs[channel * 128 + cc.as_int() as usize] = Route::Msb(slot);
+                        if let Some(lsb) = u7::try_from(cc.as_int() + 32) {
+                            ccs[channel * 128 + lsb.as_int() as usize] = Route::Lsb(slot);
+                        }
+                    }
+                    Address::Cc(cc) => {
+                        let route = &mut ccs[channel * 128 + cc.as_int() as usize];
+                        // the LSB of a 14-bit component comes first
+                        if !matches!(route, Route::Lsb(_)) {
+                            *route = Route::Component(slot);
+                        }
+                    }
+                    Address::Note(note) => {
+                        notes[channel * 128 + note.as_int() as usize] = Some(slot);
+                    }
+                    Address::PitchBend(bend) => {
+                        pitch_bends[bend.as_int() as usize] = Some(slot);
+                    }
+                    Address::Nrpn(_) => (),
+                }
+            }
+        }
+        Self {
+            components,
+            index,
+            ccs,
+            notes,
+            pitch_bends,
+        }
+    }
+    pub fn component(&self, slot: usize) -> &Component {
+        &self.components[slot]
+    }
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+    /// The slot of the component declared at `address` on `channel`.
+    pub fn slot(&self, channel: Option<u4>, address: Address) -> Option<usize> {
+        self.index.get(&(channel, address)).copied()
+    }
+    pub fn cc(&self, channel: u4, cc: u7) -> Route {
+        self.ccs[channel.as_int() as usize * 128 + cc.as_int() as usize]
+    }
+    pub fn note(&self, channel: u4, note: u7) -> Option<usize> {
+        self.notes[channel.as_int() as usize * 128 + note.as_int() as usize]
+    }
+    pub fn pitch_bend(&self, channel: u4) -> Option<usize> {
+        self.pitch_bends[channel.as_int() as usize]
+    }
+    /// The slot of the component a message at `address` on `channel` is
+    /// for, preferring one declared on that channel over one listening on
+    /// every channel.
+    pub fn find(&self, channel: u4, address: Address) -> Option<usize> {
+        self.slot(Some(channel), address)
+            .or_else(|| self.slot(None, address))
+    }
+    /// Every component at `address` that a binding on `channel` applies to.
+    pub fn matching(&self, channel: Option<u4>, address: Address) -> Vec<usize> {
+        self.index
+            .iter()
+            .filter(|((c, a), _)| {
+                *a == address && (c.is_none() || channel.is_none() || *c == channel)
+            })
+            .map(|(_, slot)| *slot)
+            .collect()
+    }
+}
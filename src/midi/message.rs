@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+
+use midly::live::{LiveEvent, SystemCommon};
+use midly::num::{u4, u7};
+use midly::MidiMessage;
+
+use crate::midi::bankswitch::BankInput;
+use crate::midi::components::Address;
+use crate::midi::controller::{BankError, Controller};
+
+/// A MIDI message the controller couldn't handle.
+#[derive(Debug)]
+pub enum MessageError {
+    /// Bytes that aren't a valid MIDI message.
+    Parse { bytes: Vec<u8>, error: midly::Error },
+    /// A bank switch the preset's bank policy refused.
+    Bank(BankError),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Parse { bytes, error } => {
+                write!(f, "couldn't parse MIDI message {:02x?}: {}", bytes, error)
+            }
+            MessageError::Bank(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for MessageError {}
+
+impl Controller {
+    /// Routes one message as received from the MIDI input, `timestamp` in
+    /// microseconds. Messages midlman has no use for, such as clock or
+    /// aftertouch, are ignored, and shown with `debug` unless they are
+    /// realtime.
+    pub fn handle_message(
+        &mut self,
+        timestamp: u64,
+        message: &[u8],
+        debug: bool,
+    ) -> Result<(), MessageError> {
+        let event = LiveEvent::parse(message).map_err(|error| MessageError::Parse {
+            bytes: message.to_vec(),
+            error,
+        })?;
+        match event {
+            LiveEvent::Midi { channel, message } => match message {
+                MidiMessage::Controller { controller, value } => {
+                    if debug {
+                        println!(
+                            "Channel {} Controller {:?}: {:?}",
+                            channel.as_int() + 1,
+                            controller,
+                            value
+                        );
+                    }
+                    if self.claims_cc(controller) {
+                        let result = self.trigger_bank(BankInput::Cc(controller, value));
+                        return self.report_bank(result, debug);
+                    }
+                    self.set_component(channel, controller, value, timestamp);
+                    if debug {
+                        self.report_value(channel, Address::Cc(controller));
+                    }
+                }
+                MidiMessage::ProgramChange { program } => {
+                    if debug {
+                        println!("Program Change {:?}", program);
+                    }
+                    let result = self.trigger_bank(BankInput::ProgramChange(program));
+                    return self.report_bank(result, debug);
+                }
+                MidiMessage::NoteOn { key, vel } => {
+                    if debug {
+                        println!(
+                            "Channel {} Note On {:?}: {:?}",
+                            channel.as_int() + 1,
+                            key,
+                            vel
+                        );
+                    }
+                    if self.claims_note(key) {
+                        let result = self.trigger_bank(BankInput::NoteOn(key, vel));
+                        return self.report_bank(result, debug);
+                    }
+                    self.set_note(channel, key, vel);
+                    if debug {
+                        self.report_value(channel, Address::Note(key));
+                    }
+                }
+                MidiMessage::NoteOff { key, vel } => {
+                    if debug {
+                        println!(
+                            "Channel {} Note Off {:?}: {:?}",
+                            channel.as_int() + 1,
+                            key,
+                            vel
+                        );
+                    }
+                    if !self.claims_note(key) {
+                        self.set_note(channel, key, u7::from(0));
+                    }
+                }
+                MidiMessage::PitchBend { bend } => {
+                    if debug {
+                        println!("Channel {} Pitch Bend: {:?}", channel.as_int() + 1, bend.0);
+                    }
+                    self.set_pitch_bend(channel, bend.0);
+                    if debug {
+                        self.report_value(channel, Address::PitchBend(channel));
+                    }
+                }
+                MidiMessage::Aftertouch { .. } | MidiMessage::ChannelAftertouch { .. } => {
+                    if debug {
+                        println!("Channel {} Ignoring {:?}", channel.as_int() + 1, message);
+                    }
+                }
+            },
+            LiveEvent::Common(SystemCommon::SysEx(bytes)) => {
+                if debug {
+                    println!("SysEx {:?}", bytes);
+                }
+                let result = self.trigger_bank(BankInput::SysEx(bytes));
+                return self.report_bank(result, debug);
+            }
+            LiveEvent::Common(common) => {
+                if debug {
+                    println!("Ignoring {:?}", common);
+                }
+            }
+            // clock, active sensing and the like arrive many times a second
+            LiveEvent::Realtime(_) => (),
+        }
+        Ok(())
+    }
+    /// Shows where the component a message went to is now, as decoded.
+    fn report_value(&self, channel: u4, address: Address) {
+        if let Some((address, value)) = self.received(channel, address) {
+            println!("  {} is at {}", address, value);
+        }
+    }
+    /// Reports a bank switch and re-syncs the surface with the new bank.
+    fn report_bank(
+        &self,
+        result: Option<Result<u7, BankError>>,
+        debug: bool,
+    ) -> Result<(), MessageError> {
+        match result {
+            Some(Ok(bank)) => {
+                if debug {
+                    println!("Bank {} {}", bank, self.bank_name(bank).unwrap_or_default());
+                }
+                self.sync();
+                Ok(())
+            }
+            Some(Err(err)) => Err(MessageError::Bank(err)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::fixtures;
+
+    fn controller() -> Controller {
+        fixtures::controller(
+            r#"
+banks: 4
+bank_triggers:
+  - program_change: {}
+  - note: { note: 36, action: next }
+"#,
+        )
+    }
+
+    #[test]
+    fn malformed_messages_are_errors() {
+        let mut controller = controller();
+        for bytes in [&[][..], &[0xb0, 5], &[0x05, 0x40], &[0xb0, 5, 200]] {
+            let result = controller.handle_message(0, bytes, false);
+            assert!(
+                matches!(result, Err(MessageError::Parse { .. })),
+                "{bytes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unused_messages_are_ignored() {
+        let mut controller = controller();
+        for bytes in [
+            &[0xf8][..],
+            &[0xfe],
+            &[0xa0, 60, 10],
+            &[0xd0, 10],
+            &[0xf1, 0],
+        ] {
+            controller.handle_message(0, bytes, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn bank_triggers_switch_banks() {
+        let mut controller = controller();
+        controller.handle_message(0, &[0xc0, 2], false).unwrap();
+        assert_eq!(controller.bank(), u7::from(2));
+        controller
+            .handle_message(0, &[0x90, 36, 100], false)
+            .unwrap();
+        assert_eq!(controller.bank(), u7::from(3));
+        // releasing the trigger doesn't switch again
+        controller.handle_message(0, &[0x80, 36, 0], false).unwrap();
+        controller.handle_message(0, &[0x90, 36, 0], false).unwrap();
+        assert_eq!(controller.bank(), u7::from(3));
+    }
+
+    #[test]
+    fn refused_bank_switches_are_errors() {
+        let mut controller = controller();
+        let result = controller.handle_message(0, &[0xc0, 9], false);
+        assert!(matches!(
+            result,
+            Err(MessageError::Bank(BankError { bank: 9, count: 4 }))
+        ));
+        assert_eq!(controller.bank(), u7::from(0));
+    }
+}
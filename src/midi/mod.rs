@@ -1,15 +1,39 @@
+mod bankswitch;
+mod ccset;
 mod components;
 mod controller;
+mod feedback;
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod layout;
+mod message;
+mod parameter;
 mod preset;
 
 use std::error::Error;
 use std::io::{stdin, stdout, Write};
 
-use midir::{MidiInput, MidiInputPort};
+use midir::{MidiInput, MidiInputPort, MidiOutput, MidiOutputPort};
 
 // re-export
-pub use controller::Controller;
-pub use preset::Preset;
+pub use bankswitch::{BankAction, BankInput, BankTrigger};
+pub use ccset::{CcSet, CcSetError, CcSetErrorKind, ParameterSet};
+pub use components::ComponentCallback;
+pub use components::{
+    Acceleration, Address, Behavior, Button, ButtonEvent, ButtonSettings, CloneBehavior, Component,
+    ComponentKind, ComponentRef, ComponentSettings, Dial, EncoderMode, Pad, Slider, Takeover,
+    TakeoverMode, Value, ValueSource, BUTTON, DIAL, KINDS, PAD, SLIDER,
+};
+pub use controller::{BankError, Banks, BindError, Controller};
+pub use feedback::{
+    Feedback, FeedbackCallback, FeedbackEntry, FeedbackOutput, FeedbackState, MidiSink, SharedSink,
+};
+pub use message::MessageError;
+pub use parameter::{Parameter, ParameterInput, ParameterKind, ParameterParser, PARAMETER_CCS};
+pub use preset::{
+    BankPolicy, ComponentList, Diagnostic, ListEntry, NrpnComponents, Preset, PresetComponents,
+    PresetError,
+};
 
 pub fn find_in_port(midi_in: &MidiInput) -> Result<MidiInputPort, Box<dyn Error>> {
     let in_ports = midi_in.ports();
@@ -33,9 +57,62 @@ pub fn find_in_port(midi_in: &MidiInput) -> Result<MidiInputPort, Box<dyn Error>
             stdin().read_line(&mut input)?;
             in_ports
                 .get(input.trim().parse::<usize>()?)
-                .map(|p| p.clone())
+                .cloned()
                 .ok_or("invalid input port selected")?
         }
     };
     Ok(in_port)
 }
+
+/// The output port of the controller whose input port is `name`. A port with
+/// the same name is preferred, as most controllers name both alike, then one
+/// whose name only differs in saying "in" or "out", as Windows drivers name
+/// them (`MIDIIN2 (X)` and `MIDIOUT2 (X)`).
+pub fn find_out_port(midi_out: &MidiOutput, name: &str) -> Option<MidiOutputPort> {
+    let ports: Vec<_> = midi_out
+        .ports()
+        .into_iter()
+        .filter_map(|p| Some((midi_out.port_name(&p).ok()?, p)))
+        .collect();
+    let key = port_key(name);
+    ports
+        .iter()
+        .find(|(n, _)| n == name)
+        .or_else(|| ports.iter().find(|(n, _)| port_key(n) == key))
+        .map(|(_, p)| p.clone())
+}
+
+/// `name` without the words that tell an input port from an output port.
+fn port_key(name: &str) -> String {
+    let name = name
+        .to_lowercase()
+        .replace("midiin", "midi")
+        .replace("midiout", "midi");
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !matches!(*word, "" | "in" | "out" | "input" | "output"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_and_output_ports_match() {
+        assert_eq!(
+            port_key("MIDIIN2 (X-Touch)"),
+            port_key("MIDIOUT2 (X-Touch)")
+        );
+        assert_eq!(port_key("Launchpad In"), port_key("Launchpad Out"));
+        assert_eq!(
+            port_key("nanoKONTROL2 Input"),
+            port_key("nanoKONTROL2 Output")
+        );
+        assert_ne!(
+            port_key("MIDIIN2 (X-Touch)"),
+            port_key("MIDIOUT3 (X-Touch)")
+        );
+        assert_ne!(port_key("Launchpad Mini"), port_key("Launchpad Pro"));
+    }
+}
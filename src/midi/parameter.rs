@@ -0,0 +1,232 @@
+use midly::num::{u14, u4, u7};
+
+use crate::midi::components::Value;
+
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+
+/// The CCs a [`ParameterParser`] reads, which can't also be components.
+pub const PARAMETER_CCS: [u8; 6] = [
+    DATA_ENTRY_MSB,
+    DATA_ENTRY_LSB,
+    NRPN_LSB,
+    NRPN_MSB,
+    RPN_LSB,
+    RPN_MSB,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// Non-registered, numbered by the device or its editor.
+    Nrpn,
+    /// Registered, numbered by the MIDI spec (pitch bend range, tuning, ...).
+    Rpn,
+}
+
+/// A complete parameter change assembled from a CC sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameter {
+    pub kind: ParameterKind,
+    pub number: u14,
+    pub value: Value,
+}
+
+/// What a CC meant to the [`ParameterParser`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterInput {
+    /// Not part of a parameter sequence, route it as a plain CC.
+    NotParameter,
+    /// Part of a sequence that isn't complete yet.
+    Pending,
+    Complete(Parameter),
+}
+
+/// The parameter selected on one channel and its pending data entry MSB.
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    kind: Option<ParameterKind>,
+    msb: Option<u7>,
+    lsb: Option<u7>,
+    data: Option<u7>,
+}
+
+impl ChannelState {
+    fn select(&mut self, kind: ParameterKind) {
+        if self.kind != Some(kind) {
+            *self = Self {
+                kind: Some(kind),
+                ..Self::default()
+            };
+        }
+        self.data = None;
+    }
+    /// The selected parameter, unless it is the RPN null (127/127) that
+    /// devices send to deselect.
+    fn selected(&self) -> Option<(ParameterKind, u14)> {
+        let (kind, msb, lsb) = (self.kind?, self.msb?, self.lsb?);
+        if kind == ParameterKind::Rpn && msb.as_int() == 127 && lsb.as_int() == 127 {
+            return None;
+        }
+        let number = ((msb.as_int() as u16) << 7) | lsb.as_int() as u16;
+        Some((kind, u14::from(number)))
+    }
+}
+
+/// Assembles NRPN (CC 99/98) and RPN (CC 101/100) parameter selections and
+/// their data entry (CC 6/38) into [`Parameter`]s, per channel. A parameter
+/// stays selected after its value, so devices may send only data entry for
+/// following changes.
+#[derive(Debug, Clone)]
+pub struct ParameterParser {
+    /// Whether data entry comes as an MSB/LSB pair. If so, a value is complete
+    /// once CC 38 arrives, otherwise on every CC 6.
+    fine: bool,
+    channels: [ChannelState; 16],
+}
+
+impl ParameterParser {
+    pub fn new(fine: bool) -> Self {
+        Self {
+            fine,
+            channels: [ChannelState::default(); 16],
+        }
+    }
+    pub fn feed(&mut self, channel: u4, cc: u7, value: u7) -> ParameterInput {
+        let state = &mut self.channels[channel.as_int() as usize];
+        match cc.as_int() {
+            NRPN_MSB | RPN_MSB => {
+                state.select(Self::kind(cc));
+                state.msb = Some(value);
+            }
+            NRPN_LSB | RPN_LSB => {
+                state.select(Self::kind(cc));
+                state.lsb = Some(value);
+            }
+            DATA_ENTRY_MSB => {
+                let Some((kind, number)) = state.selected() else {
+                    return ParameterInput::NotParameter;
+                };
+                if !self.fine {
+                    return ParameterInput::Complete(Parameter {
+                        kind,
+                        number,
+                        value: Value::U7(value),
+                    });
+                }
+                state.data = Some(value);
+            }
+            DATA_ENTRY_LSB => {
+                let Some((kind, number)) = state.selected() else {
+                    return ParameterInput::NotParameter;
+                };
+                let Some(msb) = state.data.filter(|_| self.fine) else {
+                    return ParameterInput::Pending;
+                };
+                let value = ((msb.as_int() as u16) << 7) | value.as_int() as u16;
+                return ParameterInput::Complete(Parameter {
+                    kind,
+                    number,
+                    value: Value::U14(u14::from(value)),
+                });
+            }
+            _ => return ParameterInput::NotParameter,
+        }
+        ParameterInput::Pending
+    }
+    fn kind(cc: u7) -> ParameterKind {
+        match cc.as_int() {
+            NRPN_MSB | NRPN_LSB => ParameterKind::Nrpn,
+            _ => ParameterKind::Rpn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(parser: &mut ParameterParser, channel: u8, messages: &[(u8, u8)]) -> ParameterInput {
+        let mut input = ParameterInput::NotParameter;
+        for (cc, value) in messages {
+            input = parser.feed(u4::from(channel), u7::from(*cc), u7::from(*value));
+        }
+        input
+    }
+
+    fn nrpn(number: u16, value: Value) -> ParameterInput {
+        ParameterInput::Complete(Parameter {
+            kind: ParameterKind::Nrpn,
+            number: u14::from(number),
+            value,
+        })
+    }
+
+    #[test]
+    fn fine_values_complete_on_the_lsb() {
+        let mut parser = ParameterParser::new(true);
+        assert_eq!(
+            feed(&mut parser, 0, &[(99, 1), (98, 2), (6, 3)]),
+            ParameterInput::Pending
+        );
+        assert_eq!(
+            feed(&mut parser, 0, &[(38, 4)]),
+            nrpn(130, Value::U14(u14::from((3 << 7) | 4)))
+        );
+        // the parameter stays selected for the next value
+        assert_eq!(
+            feed(&mut parser, 0, &[(6, 5), (38, 0)]),
+            nrpn(130, Value::U14(u14::from(5 << 7)))
+        );
+    }
+
+    #[test]
+    fn coarse_values_complete_on_the_msb() {
+        let mut parser = ParameterParser::new(false);
+        assert_eq!(
+            feed(&mut parser, 0, &[(99, 0), (98, 7), (6, 100)]),
+            nrpn(7, Value::U7(u7::from(100)))
+        );
+        assert_eq!(feed(&mut parser, 0, &[(38, 1)]), ParameterInput::Pending);
+    }
+
+    #[test]
+    fn the_rpn_null_deselects() {
+        let mut parser = ParameterParser::new(true);
+        feed(&mut parser, 0, &[(99, 0), (98, 7)]);
+        assert_eq!(
+            feed(&mut parser, 0, &[(101, 127), (100, 127)]),
+            ParameterInput::Pending
+        );
+        assert_eq!(
+            feed(&mut parser, 0, &[(6, 1)]),
+            ParameterInput::NotParameter
+        );
+        assert_eq!(
+            feed(&mut parser, 0, &[(38, 1)]),
+            ParameterInput::NotParameter
+        );
+    }
+
+    #[test]
+    fn channels_are_separate() {
+        let mut parser = ParameterParser::new(true);
+        feed(&mut parser, 0, &[(99, 0), (98, 7), (6, 3)]);
+        // nothing is selected on channel 2, and its data entry is plain CCs
+        assert_eq!(
+            feed(&mut parser, 1, &[(6, 9)]),
+            ParameterInput::NotParameter
+        );
+        assert_eq!(
+            feed(&mut parser, 1, &[(38, 9)]),
+            ParameterInput::NotParameter
+        );
+        assert_eq!(
+            feed(&mut parser, 0, &[(38, 1)]),
+            nrpn(7, Value::U14(u14::from((3 << 7) | 1)))
+        );
+    }
+}
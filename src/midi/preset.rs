@@ -1,13 +1,1309 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use midly::num::{u4, u7};
 use serde::Deserialize;
 
+use crate::midi::bankswitch::{BankAction, BankTrigger};
+use crate::midi::ccset::{CcSet, CcSetError, ParameterSet};
+use crate::midi::components::{
+    Acceleration, Address, ButtonSettings, Component, ComponentKind, ComponentRef,
+    ComponentSettings, EncoderMode, TakeoverMode, KINDS, SLIDER,
+};
+use crate::midi::feedback::FeedbackEntry;
+use crate::midi::parameter::PARAMETER_CCS;
+
+/// Every CC (`false`) and note (`true`) in use, with the channel and name of
+/// each list using it.
+type Assignments = HashMap<(bool, u7), Vec<(Option<u4>, &'static str)>>;
+
+/// A parsed component list: its kind, channel and numbers.
+type ParsedList = (&'static ComponentKind, Option<u4>, CcSet);
+
+/// A setting of individual components, by the channel and address they are
+/// listed under. One without a channel applies on every channel.
+type Settings<T> = HashMap<(Option<u4>, Address), T>;
+
+/// The setting for the component at `address` on `channel`, preferring one
+/// listed on its channel over one for every channel.
+fn setting<T: Copy>(settings: &Settings<T>, channel: Option<u4>, address: Address) -> Option<T> {
+    settings
+        .get(&(channel, address))
+        .or_else(|| settings.get(&(None, address)))
+        .copied()
+}
+
+/// The 0-based channel of a 1-based one, `None` if it isn't 1-16.
+fn zero_based(channel: u8) -> Option<u4> {
+    channel.checked_sub(1).and_then(u4::try_from)
+}
+
+/// The nine banks midlman has always used, kept as the default.
+const DEFAULT_BANKS: u8 = 9;
+
+fn default_banks() -> u8 {
+    DEFAULT_BANKS
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Preset {
+    /// Channel (1-16) every component listens on, unless overridden per list.
+    /// Without one, components respond on every channel.
+    #[serde(default)]
+    pub channel: Option<u8>,
+    #[serde(default = "default_banks")]
+    pub banks: u8,
+    #[serde(default)]
+    pub bank_names: Vec<String>,
+    #[serde(default)]
+    pub bank_policy: BankPolicy,
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub bank_triggers: Vec<BankTrigger>,
     pub components: PresetComponents,
+    /// Buttons (CCs) or pads (notes) that switch every other component to
+    /// another layer while held, modifier `n` (from 1) to layer `n`.
+    #[serde(default)]
+    pub modifiers: Vec<ComponentRef>,
+    /// How buttons read presses, long presses and double presses.
+    #[serde(default)]
+    pub button_behavior: ButtonSettings,
+    /// Messages that show each component's state on the controller.
+    #[serde(default)]
+    pub feedback: Vec<FeedbackEntry>,
+}
+
+/// What to do with a bank switch beyond the last bank.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BankPolicy {
+    /// Ignore the request and stay on the active bank.
+    #[default]
+    Reject,
+    /// Switch to the last bank instead.
+    Clamp,
+}
+
+/// The numbers of a component list: one list on the list's channel, or a
+/// list per channel (1-16), e.g. `{1: "3-11", 2: "3-11"}` for a device that
+/// repeats its faders on two channels.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ComponentList {
+    Numbers(String),
+    PerChannel(BTreeMap<u8, String>),
+}
+
+impl ComponentList {
+    /// Each list of numbers, with its channel (1-16) if it was given one.
+    pub fn entries(&self) -> Vec<(Option<u8>, &str)> {
+        match self {
+            ComponentList::Numbers(numbers) => vec![(None, numbers.as_str())],
+            ComponentList::PerChannel(lists) => lists
+                .iter()
+                .map(|(channel, numbers)| (Some(*channel), numbers.as_str()))
+                .collect(),
+        }
+    }
+    /// Every CC in the list as a key of [`Settings`]. Entries on channels
+    /// outside 1-16 are skipped, [`Preset::validate`] reports them.
+    fn settings_keys(&self, list: &'static str) -> Result<Vec<(Option<u4>, Address)>, PresetError> {
+        let mut keys = Vec::new();
+        for (channel, ccs) in self.entries() {
+            let channel = match channel.map(zero_based) {
+                Some(None) => continue,
+                Some(channel) => channel,
+                None => None,
+            };
+            let set: CcSet = ccs
+                .parse()
+                .map_err(|error| PresetError::List { list, error })?;
+            keys.extend(set.iter().map(|cc| (channel, Address::Cc(cc))));
+        }
+        Ok(keys)
+    }
+}
+
+/// Every NRPN number in `numbers` as a key of [`Settings`].
+fn nrpn_settings_keys(numbers: &str) -> Result<Vec<(Option<u4>, Address)>, PresetError> {
+    let set: ParameterSet = numbers.parse().map_err(|error| PresetError::List {
+        list: "nrpn",
+        error,
+    })?;
+    Ok(set
+        .iter()
+        .map(|number| (None, Address::Nrpn(number)))
+        .collect())
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PresetComponents {
-    pub sliders: String,
-    pub buttons: String,
-    pub dials: String,
+    /// MSB CCs (0-31) of sliders or dials that send 14-bit values, paired
+    /// with CC + 32 as the LSB.
+    #[serde(default)]
+    pub high_resolution: Option<String>,
+    /// Channels (1-16) with a fader that sends pitch bend, as Mackie-style
+    /// surfaces do for their motor faders. These act as 14-bit sliders.
+    #[serde(default)]
+    pub pitchbend: Option<String>,
+    /// Dials that are endless encoders, keyed by how they encode their
+    /// steps. Dials not listed are absolute.
+    #[serde(default)]
+    pub encoders: HashMap<EncoderMode, ComponentList>,
+    /// Acceleration curves for encoders, keyed by a list of their CCs.
+    #[serde(default)]
+    pub acceleration: HashMap<String, AccelerationEntry>,
+    /// Sliders and dials with soft takeover, keyed by mode. Components not
+    /// listed jump.
+    #[serde(default)]
+    pub takeover: HashMap<TakeoverMode, ComponentList>,
+    /// Components addressed by NRPN number rather than CC.
+    #[serde(default)]
+    pub nrpn: Option<NrpnComponents>,
+    /// Per-list channel (1-16) overrides, keyed by list name, for lists
+    /// that aren't given per channel.
+    #[serde(default)]
+    pub channels: HashMap<String, u8>,
+    /// The CC or note numbers of each kind of component, keyed by the name
+    /// of its list in [`KINDS`], such as `sliders`.
+    #[serde(flatten)]
+    pub lists: BTreeMap<String, ComponentList>,
+}
+
+/// An acceleration curve, for the encoders on one channel (1-16) or on
+/// every channel.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct AccelerationEntry {
+    #[serde(default)]
+    pub channel: Option<u8>,
+    #[serde(flatten)]
+    pub curve: Acceleration,
+}
+
+/// One list of numbers of a [`ComponentList`].
+#[derive(Debug, Clone, Copy)]
+pub struct ListEntry<'a> {
+    pub list: &'static str,
+    pub kind: &'static ComponentKind,
+    /// The channel (1-16) the numbers are listed under, if the list is
+    /// given per channel.
+    pub channel: Option<u8>,
+    pub numbers: &'a str,
+}
+
+/// NRPN-addressed components, as lists of parameter numbers (0-16383),
+/// and their settings.
+#[derive(Debug, Deserialize)]
+pub struct NrpnComponents {
+    /// Whether the device sends data entry as an MSB/LSB pair (CC 6 then
+    /// 38) for 14-bit values, or only the MSB.
+    #[serde(default = "default_fine")]
+    pub fine: bool,
+    #[serde(default)]
+    pub encoders: HashMap<EncoderMode, String>,
+    #[serde(default)]
+    pub acceleration: HashMap<String, Acceleration>,
+    #[serde(default)]
+    pub takeover: HashMap<TakeoverMode, String>,
+    /// The parameter numbers of each kind of component, keyed like
+    /// [`PresetComponents::lists`].
+    #[serde(flatten)]
+    pub lists: BTreeMap<String, String>,
+}
+
+fn default_fine() -> bool {
+    true
+}
+
+impl NrpnComponents {
+    /// Every NRPN list of a kind addressed by number, by kind, in the order
+    /// of [`KINDS`].
+    pub fn lists(&self) -> Vec<(&'static ComponentKind, &str)> {
+        KINDS
+            .iter()
+            .filter(|kind| !kind.note)
+            .filter_map(|kind| Some((kind, self.lists.get(kind.list)?.as_str())))
+            .collect()
+    }
+    /// Parses every NRPN list, stopping at the first invalid one.
+    pub fn parse(&self) -> Result<Vec<(&'static ComponentKind, ParameterSet)>, PresetError> {
+        self.lists()
+            .into_iter()
+            .map(|(kind, numbers)| {
+                numbers
+                    .parse::<ParameterSet>()
+                    .map(|set| (kind, set))
+                    .map_err(|error| PresetError::List {
+                        list: "nrpn",
+                        error,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A part of a preset the controller can't be built from.
+#[derive(Debug)]
+pub enum PresetError {
+    /// A component list that isn't a valid [`CcSet`].
+    List {
+        list: &'static str,
+        error: CcSetError,
+    },
+    /// A modifier that doesn't name a component, for layer `layer`.
+    Modifier { layer: usize, error: String },
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::List { list, error } => write!(f, "invalid {} list, {}", list, error),
+            PresetError::Modifier { layer, error } => {
+                write!(f, "invalid modifier for layer {}, {}", layer, error)
+            }
+        }
+    }
+}
+
+impl Error for PresetError {}
+
+/// A problem found by [`Preset::validate`], with the 1-based line of the
+/// offending key when it could be located in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: Option<usize>,
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}: {}: {}", line, self.key, self.message),
+            None => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// Every CC of a setting list, such as `encoders`, with the channel it is
+/// listed under. Invalid entries and channels are added to `messages`.
+fn setting_ccs(list: &ComponentList, messages: &mut Vec<String>) -> Vec<(Option<u4>, u7)> {
+    let mut ccs = Vec::new();
+    for (channel, numbers) in list.entries() {
+        let (set, errors) = CcSet::parse_all(numbers);
+        messages.extend(errors.iter().map(|e| e.to_string()));
+        let channel = match channel.map(|channel| (channel, zero_based(channel))) {
+            Some((channel, None)) => {
+                messages.push(format!("channel {} is not between 1 and 16", channel));
+                continue;
+            }
+            Some((_, channel)) => channel,
+            None => None,
+        };
+        ccs.extend(set.iter().map(|cc| (channel, cc)));
+    }
+    ccs
+}
+
+/// A CC as named in diagnostics, with its channel if it has one.
+fn cc_name(channel: Option<u4>, cc: u7) -> String {
+    match channel {
+        Some(channel) => format!("CC {} on channel {}", cc, channel.as_int() + 1),
+        None => format!("CC {}", cc),
+    }
+}
+
+/// Whether `cc` is in a list of a kind that `accepts`, on `channel`, or on
+/// any channel without one.
+fn is_listed(
+    assigned: &Assignments,
+    channel: Option<u4>,
+    cc: u7,
+    accepts: impl Fn(&ComponentKind) -> bool,
+) -> bool {
+    assigned.get(&(false, cc)).is_some_and(|lists| {
+        lists.iter().any(|(c, list)| {
+            (channel.is_none() || *c == channel) && ComponentKind::named(list).is_some_and(&accepts)
+        })
+    })
+}
+
+impl Preset {
+    /// Checks the bank settings, and every component list for invalid entries,
+    /// empty lists and CCs or notes assigned more than once. `source` is the YAML the
+    /// preset was read from and is only used to attach line numbers.
+    pub fn validate(&self, source: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        if !(1..=128).contains(&self.banks) {
+            diagnostics.push(Diagnostic {
+                line: Self::find_line(source, "banks"),
+                key: "banks",
+                message: format!("{} is not between 1 and 128", self.banks),
+            });
+        }
+        if self.bank_names.len() > self.banks as usize {
+            diagnostics.push(Diagnostic {
+                line: Self::find_line(source, "bank_names"),
+                key: "bank_names",
+                message: format!(
+                    "{} names given for {} banks",
+                    self.bank_names.len(),
+                    self.banks
+                ),
+            });
+        }
+        diagnostics.extend(self.validate_kinds(source));
+        diagnostics.extend(self.validate_channels(source));
+        diagnostics.extend(self.validate_triggers(source));
+        // CCs and notes are separate number spaces, keyed by `is_note`. A
+        // number may repeat across channels, but a list listening on every
+        // channel (`None`) clashes with all of them.
+        let mut assigned: Assignments = HashMap::new();
+        for trigger in &self.bank_triggers {
+            let number = match trigger {
+                BankTrigger::Cc { cc, .. } => u7::try_from(*cc).map(|cc| (false, cc)),
+                BankTrigger::Note { note, .. } => u7::try_from(*note).map(|note| (true, note)),
+                _ => None,
+            };
+            if let Some(number) = number {
+                assigned
+                    .entry(number)
+                    .or_default()
+                    .push((None, "bank_triggers"));
+            }
+        }
+        if self.components.nrpn.is_some() {
+            for cc in PARAMETER_CCS {
+                assigned
+                    .entry((false, u7::from(cc)))
+                    .or_default()
+                    .push((None, "nrpn"));
+            }
+        }
+        for entry in self.components.lists() {
+            let ListEntry {
+                list,
+                kind,
+                numbers: ccs,
+                ..
+            } = entry;
+            let name = if kind.note { "note" } else { "CC" };
+            let channel = self.components.channel(&entry, self.channel);
+            let line = Self::find_line(source, list);
+            let mut report = |message: String| {
+                diagnostics.push(Diagnostic {
+                    line,
+                    key: list,
+                    message,
+                })
+            };
+            if ccs.trim().is_empty() {
+                report("list is empty".to_string());
+                continue;
+            }
+            // reported by validate_channels
+            if entry.channel.is_some() && channel.is_none() {
+                continue;
+            }
+            let (set, errors) = CcSet::parse_all(ccs);
+            errors.iter().for_each(|error| report(error.to_string()));
+            for cc in set.iter() {
+                let others = assigned.entry((kind.note, cc)).or_default();
+                let clash = others
+                    .iter()
+                    .find(|(c, _)| c.is_none() || channel.is_none() || *c == channel);
+                match clash {
+                    Some(&(_, other)) if other == list => {
+                        report(format!("{} {} is listed more than once", name, cc))
+                    }
+                    Some(&(_, other)) => {
+                        report(format!("{} {} is already assigned to {}", name, cc, other))
+                    }
+                    None => others.push((channel, list)),
+                }
+            }
+        }
+        diagnostics.extend(self.validate_high_resolution(source, &assigned));
+        diagnostics.extend(self.validate_pitch_bend(source));
+        diagnostics.extend(self.validate_encoders(source, &assigned));
+        diagnostics.extend(self.validate_acceleration(source));
+        diagnostics.extend(self.validate_nrpn(source));
+        diagnostics.extend(self.validate_feedback(source));
+        diagnostics.extend(self.validate_takeover(source, &assigned));
+        diagnostics.extend(self.validate_button_behavior(source));
+        diagnostics.extend(self.validate_modifiers(source, &assigned));
+        diagnostics
+    }
+
+    fn validate_modifiers(&self, source: &str, assigned: &Assignments) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "modifiers");
+        let mut messages = Vec::new();
+        let mut seen = Vec::new();
+        for (i, modifier) in self.modifiers.iter().enumerate() {
+            let mut report =
+                |message: String| messages.push(format!("layer {}: {}", i + 1, message));
+            if let Err(err) = modifier.channel() {
+                report(err);
+            }
+            let address = match modifier.address() {
+                Ok(address) => address,
+                Err(err) => {
+                    report(err);
+                    continue;
+                }
+            };
+            let (is_note, number) = match address {
+                Address::Cc(cc) => (false, cc),
+                Address::Note(note) => (true, note),
+                _ => {
+                    report(format!(
+                        "{} can't be a modifier, only {}",
+                        address,
+                        ComponentKind::names(|kind| !kind.absolute)
+                    ));
+                    continue;
+                }
+            };
+            // modifiers are pressed and released, like the kinds that aren't
+            // absolute
+            let can_modify = |kind: &ComponentKind| !kind.absolute && kind.note == is_note;
+            let listed = assigned.get(&(is_note, number)).is_some_and(|lists| {
+                lists
+                    .iter()
+                    .any(|(_, list)| ComponentKind::named(list).is_some_and(can_modify))
+            });
+            if !listed {
+                report(format!(
+                    "{} is not in {}",
+                    address,
+                    ComponentKind::names(can_modify)
+                ));
+            }
+            if seen.contains(&address) {
+                report(format!("{} is already a modifier", address));
+            }
+            seen.push(address);
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "modifiers",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_button_behavior(&self, source: &str) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "button_behavior");
+        let settings = &self.button_behavior;
+        let mut messages = Vec::new();
+        if !(1..=127).contains(&settings.threshold) {
+            messages.push(format!(
+                "threshold {} is not between 1 and 127",
+                settings.threshold
+            ));
+        }
+        [
+            ("long_press_ms", settings.long_press_ms),
+            ("double_press_ms", settings.double_press_ms),
+        ]
+        .iter()
+        .filter(|(_, ms)| *ms == 0)
+        .for_each(|(key, _)| messages.push(format!("{} must be above 0", key)));
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "button_behavior",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_takeover(&self, source: &str, assigned: &Assignments) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "takeover");
+        let encoders = self.components.encoders().unwrap_or_default();
+        let absolute = ComponentKind::names(|kind| kind.absolute);
+        let mut seen = HashMap::new();
+        let mut messages = Vec::new();
+        let mut modes: Vec<_> = self.components.takeover.iter().collect();
+        modes.sort_by_key(|(mode, _)| **mode);
+        for (mode, list) in modes {
+            for (channel, cc) in setting_ccs(list, &mut messages) {
+                let name = cc_name(channel, cc);
+                if !is_listed(assigned, channel, cc, |kind| kind.absolute) {
+                    messages.push(format!("{} is not in {}", name, absolute));
+                }
+                if setting(&encoders, channel, Address::Cc(cc))
+                    .is_some_and(|mode| mode != EncoderMode::Absolute)
+                {
+                    messages.push(format!("{} is a relative encoder", name));
+                }
+                if let Some(other) = seen.insert((channel, cc), mode) {
+                    messages.push(format!("{} is listed as both {} and {}", name, other, mode));
+                }
+            }
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "takeover",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_feedback(&self, source: &str) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "feedback");
+        self.feedback
+            .iter()
+            .enumerate()
+            .flat_map(|(i, entry)| {
+                entry
+                    .problems()
+                    .into_iter()
+                    .map(move |problem| format!("entry {}: {}", i + 1, problem))
+            })
+            .map(|message| Diagnostic {
+                line,
+                key: "feedback",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_nrpn(&self, source: &str) -> Vec<Diagnostic> {
+        let Some(nrpn) = &self.components.nrpn else {
+            return Vec::new();
+        };
+        let line = Self::find_line(source, "nrpn");
+        let mut seen: HashMap<_, &ComponentKind> = HashMap::new();
+        let mut messages = Vec::new();
+        for list in nrpn.lists.keys() {
+            match ComponentKind::named(list) {
+                Some(kind) if kind.note => {
+                    messages.push(format!("{} are addressed by note, not NRPN", list))
+                }
+                Some(_) => (),
+                None => messages.push(format!(
+                    "'{}' is not a kind of component, expected {}",
+                    list,
+                    ComponentKind::names(|kind| !kind.note)
+                )),
+            }
+        }
+        for (kind, numbers) in nrpn.lists() {
+            let list = kind.list;
+            if numbers.trim().is_empty() {
+                messages.push(format!("{}: list is empty", list));
+                continue;
+            }
+            let (set, errors) = ParameterSet::parse_all(numbers);
+            messages.extend(errors.iter().map(|e| format!("{}: {}", list, e)));
+            for number in set.iter() {
+                match seen.insert(number, kind) {
+                    Some(other) if other.list == list => messages.push(format!(
+                        "{}: NRPN {} is listed more than once",
+                        list, number
+                    )),
+                    Some(other) => messages.push(format!(
+                        "{}: NRPN {} is already assigned to {}",
+                        list, number, other.list
+                    )),
+                    None => (),
+                }
+            }
+        }
+        // the settings of NRPN components, by parameter number
+        let encoders = self.components.encoders().unwrap_or_default();
+        let relative = |number| {
+            encoders
+                .get(&(None, Address::Nrpn(number)))
+                .is_some_and(|mode| *mode != EncoderMode::Absolute)
+        };
+        let mut check = |list: &str, numbers: &str, accepts: fn(&ComponentKind) -> bool| {
+            let (set, errors) = ParameterSet::parse_all(numbers);
+            messages.extend(errors.iter().map(|e| format!("{}: {}", list, e)));
+            for number in set.iter() {
+                if !seen.get(&number).is_some_and(|kind| accepts(kind)) {
+                    messages.push(format!(
+                        "{}: NRPN {} is not in {}",
+                        list,
+                        number,
+                        ComponentKind::names(accepts)
+                    ));
+                }
+            }
+            set
+        };
+        let mut modes: Vec<_> = nrpn.encoders.iter().collect();
+        modes.sort_by_key(|(mode, _)| **mode);
+        let mut seen_modes = HashMap::new();
+        let mut problems = Vec::new();
+        for (mode, numbers) in modes {
+            for number in check("encoders", numbers, |kind| kind.encoder).iter() {
+                if let Some(other) = seen_modes.insert(number, mode) {
+                    problems.push(format!(
+                        "encoders: NRPN {} is listed as both {} and {}",
+                        number, other, mode
+                    ));
+                }
+            }
+        }
+        let mut modes: Vec<_> = nrpn.takeover.iter().collect();
+        modes.sort_by_key(|(mode, _)| **mode);
+        let mut seen_modes = HashMap::new();
+        for (mode, numbers) in modes {
+            for number in check("takeover", numbers, |kind| kind.absolute).iter() {
+                if relative(number) {
+                    problems.push(format!("takeover: NRPN {} is a relative encoder", number));
+                }
+                if let Some(other) = seen_modes.insert(number, mode) {
+                    problems.push(format!(
+                        "takeover: NRPN {} is listed as both {} and {}",
+                        number, other, mode
+                    ));
+                }
+            }
+        }
+        let mut curves: Vec<_> = nrpn.acceleration.iter().collect();
+        curves.sort_by_key(|(numbers, _)| *numbers);
+        for (numbers, acceleration) in curves {
+            for number in check("acceleration", numbers, |kind| kind.encoder).iter() {
+                if !relative(number) {
+                    problems.push(format!(
+                        "acceleration: NRPN {} is not a relative encoder",
+                        number
+                    ));
+                }
+            }
+            problems.extend(
+                acceleration
+                    .problems()
+                    .into_iter()
+                    .map(|problem| format!("acceleration: {}: {}", numbers, problem)),
+            );
+        }
+        messages
+            .into_iter()
+            .chain(problems)
+            .map(|message| Diagnostic {
+                line,
+                key: "nrpn",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_acceleration(&self, source: &str) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "acceleration");
+        let encoders = self.components.encoders().unwrap_or_default();
+        let mut messages = Vec::new();
+        let mut curves: Vec<_> = self.components.acceleration.iter().collect();
+        curves.sort_by_key(|(ccs, _)| *ccs);
+        for (ccs, entry) in curves {
+            let channel = match entry.channel.map(zero_based) {
+                Some(None) => {
+                    messages.push(format!(
+                        "{}: channel {} is not between 1 and 16",
+                        ccs,
+                        entry.channel.unwrap_or_default()
+                    ));
+                    continue;
+                }
+                Some(channel) => channel,
+                None => None,
+            };
+            let (set, errors) = CcSet::parse_all(ccs);
+            messages.extend(errors.iter().map(|e| e.to_string()));
+            for cc in set.iter() {
+                if setting(&encoders, channel, Address::Cc(cc))
+                    .is_none_or(|mode| mode == EncoderMode::Absolute)
+                {
+                    messages.push(format!(
+                        "{} is not a relative encoder",
+                        cc_name(channel, cc)
+                    ));
+                }
+            }
+            messages.extend(
+                entry
+                    .curve
+                    .problems()
+                    .into_iter()
+                    .map(|problem| format!("{}: {}", ccs, problem)),
+            );
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "acceleration",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_encoders(&self, source: &str, assigned: &Assignments) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "encoders");
+        let high_resolution = self
+            .components
+            .high_resolution
+            .as_deref()
+            .map(|ccs| CcSet::parse_all(ccs).0)
+            .unwrap_or_default();
+        let encoders = ComponentKind::names(|kind| kind.encoder);
+        let mut seen = HashMap::new();
+        let mut messages = Vec::new();
+        let mut modes: Vec<_> = self.components.encoders.iter().collect();
+        modes.sort_by_key(|(mode, _)| **mode);
+        for (mode, list) in modes {
+            for (channel, cc) in setting_ccs(list, &mut messages) {
+                let name = cc_name(channel, cc);
+                if !is_listed(assigned, channel, cc, |kind| kind.encoder) {
+                    messages.push(format!("{} is not in {}", name, encoders));
+                }
+                if *mode != EncoderMode::Absolute && high_resolution.iter().any(|m| m == cc) {
+                    messages.push(format!("{} can't be both relative and 14-bit", name));
+                }
+                if let Some(other) = seen.insert((channel, cc), mode) {
+                    messages.push(format!("{} is listed as both {} and {}", name, other, mode));
+                }
+            }
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "encoders",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_pitch_bend(&self, source: &str) -> Vec<Diagnostic> {
+        let Some(channels) = &self.components.pitchbend else {
+            return Vec::new();
+        };
+        let line = Self::find_line(source, "pitchbend");
+        let (set, errors) = CcSet::parse_all(channels);
+        let mut messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        if channels.trim().is_empty() {
+            messages.push("list is empty".to_string());
+        }
+        set.iter()
+            .filter(|c| !(1..=16).contains(&c.as_int()))
+            .for_each(|c| messages.push(format!("channel {} is not between 1 and 16", c)));
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "pitchbend",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_high_resolution(&self, source: &str, assigned: &Assignments) -> Vec<Diagnostic> {
+        let Some(ccs) = &self.components.high_resolution else {
+            return Vec::new();
+        };
+        let line = Self::find_line(source, "high_resolution");
+        let (set, errors) = CcSet::parse_all(ccs);
+        let mut messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        for msb in set.iter() {
+            if msb.as_int() >= 32 {
+                messages.push(format!("CC {} is not an MSB, it must be 0-31", msb));
+                continue;
+            }
+            if !is_listed(assigned, None, msb, |kind| kind.absolute) {
+                messages.push(format!(
+                    "CC {} is not in {}",
+                    msb,
+                    ComponentKind::names(|kind| kind.absolute)
+                ));
+            }
+            let lsb = u7::from(msb.as_int() + 32);
+            if let Some((_, list)) = assigned.get(&(false, lsb)).and_then(|l| l.first()) {
+                messages.push(format!(
+                    "CC {} is the LSB of CC {} but is assigned to {}",
+                    lsb, msb, list
+                ));
+            }
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "high_resolution",
+                message,
+            })
+            .collect()
+    }
+
+    fn validate_kinds(&self, source: &str) -> Vec<Diagnostic> {
+        self.components
+            .lists
+            .keys()
+            .filter(|list| ComponentKind::named(list).is_none())
+            .map(|list| Diagnostic {
+                line: Self::find_line(source, list),
+                key: "components",
+                message: format!(
+                    "'{}' is not a kind of component, expected {}",
+                    list,
+                    ComponentKind::names(|_| true)
+                ),
+            })
+            .collect()
+    }
+
+    fn validate_channels(&self, source: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let out_of_range = |channel: u8| !(1..=16).contains(&channel);
+        if let Some(channel) = self.channel.filter(|c| out_of_range(*c)) {
+            diagnostics.push(Diagnostic {
+                line: Self::find_line(source, "channel"),
+                key: "channel",
+                message: format!("{} is not between 1 and 16", channel),
+            });
+        }
+        let lists = self.components.lists();
+        for entry in &lists {
+            if let Some(channel) = entry.channel.filter(|c| out_of_range(*c)) {
+                diagnostics.push(Diagnostic {
+                    line: Self::find_line(source, entry.list),
+                    key: entry.list,
+                    message: format!("channel {} is not between 1 and 16", channel),
+                });
+            }
+        }
+        let line = Self::find_line(source, "channels");
+        for (list, channel) in &self.components.channels {
+            let is_nrpn = list == "nrpn" && self.components.nrpn.is_some();
+            let entries: Vec<_> = lists.iter().filter(|e| e.list == list).collect();
+            if entries.iter().any(|e| e.channel.is_some()) {
+                diagnostics.push(Diagnostic {
+                    line,
+                    key: "channels",
+                    message: format!("{} is already given per channel", list),
+                });
+            }
+            if !is_nrpn && entries.is_empty() {
+                diagnostics.push(Diagnostic {
+                    line,
+                    key: "channels",
+                    message: format!("'{}' is not a component list", list),
+                });
+            }
+            if out_of_range(*channel) {
+                diagnostics.push(Diagnostic {
+                    line,
+                    key: "channels",
+                    message: format!("{}: {} is not between 1 and 16", list, channel),
+                });
+            }
+        }
+        diagnostics
+    }
+
+    fn validate_triggers(&self, source: &str) -> Vec<Diagnostic> {
+        let line = Self::find_line(source, "bank_triggers");
+        let mut messages = Vec::new();
+        for trigger in &self.bank_triggers {
+            let (number, action) = match trigger {
+                BankTrigger::Cc { cc, action } => (Some(("CC", *cc)), Some(*action)),
+                BankTrigger::Note { note, action } => (Some(("note", *note)), Some(*action)),
+                BankTrigger::ProgramChange { programs } => {
+                    if programs.len() > self.banks as usize {
+                        messages.push(format!(
+                            "{} programs given for {} banks",
+                            programs.len(),
+                            self.banks
+                        ));
+                    }
+                    programs
+                        .iter()
+                        .filter(|p| **p > 127)
+                        .for_each(|p| messages.push(format!("program {} is above 127", p)));
+                    (None, None)
+                }
+                BankTrigger::Sysex { prefix, .. } => {
+                    prefix.iter().filter(|b| **b > 127).for_each(|b| {
+                        messages.push(format!("SysEx byte {:#04x} is above 0x7f", b))
+                    });
+                    (None, None)
+                }
+            };
+            if let Some((kind, n)) = number.filter(|(_, n)| *n > 127) {
+                messages.push(format!("{} {} is above 127", kind, n));
+            }
+            if let Some(BankAction::Bank(bank)) = action {
+                if bank >= self.banks {
+                    messages.push(format!(
+                        "bank {} is out of range, the preset has {} banks",
+                        bank, self.banks
+                    ));
+                }
+            }
+        }
+        messages
+            .into_iter()
+            .map(|message| Diagnostic {
+                line,
+                key: "bank_triggers",
+                message,
+            })
+            .collect()
+    }
+
+    fn find_line(source: &str, list: &str) -> Option<usize> {
+        let key = format!("{}:", list);
+        source
+            .lines()
+            .position(|line| line.trim_start().starts_with(&key))
+            .map(|i| i + 1)
+    }
+}
+
+impl Preset {
+    /// Every component the preset declares, set up with its encoder mode,
+    /// acceleration, soft takeover and button behavior.
+    pub fn layout(&self) -> Result<Vec<Component>, PresetError> {
+        let encoders = self.components.encoders()?;
+        let accelerations = self.components.accelerations()?;
+        let takeover = self.components.takeover()?;
+        let component = |kind: &ComponentKind, channel: Option<u4>, address: Address| {
+            let settings = ComponentSettings {
+                takeover: setting(&takeover, channel, address).unwrap_or_default(),
+                encoder: setting(&encoders, channel, address).unwrap_or_default(),
+                acceleration: setting(&accelerations, channel, address),
+                buttons: self.button_behavior,
+            };
+            Component::new(channel, address, (kind.build)(&settings))
+        };
+        let mut layout = Vec::new();
+        for (kind, channel, set) in self.components.parse(self.channel)? {
+            for n in set.iter() {
+                let address = if kind.note {
+                    Address::Note(n)
+                } else {
+                    Address::Cc(n)
+                };
+                layout.push(component(kind, channel, address));
+            }
+        }
+        // pitch-bend faders are sliders, addressed by channel
+        for channel in self.components.pitch_bend()? {
+            layout.push(component(&SLIDER, None, Address::PitchBend(channel)));
+        }
+        if let Some(nrpn) = &self.components.nrpn {
+            let channel = self
+                .components
+                .list_channel("nrpn", self.channel)
+                .and_then(zero_based);
+            for (kind, set) in nrpn.parse()? {
+                for number in set.iter() {
+                    layout.push(component(kind, channel, Address::Nrpn(number)));
+                }
+            }
+        }
+        Ok(layout)
+    }
+}
+
+impl PresetComponents {
+    /// Every component list in the preset of a kind in [`KINDS`], one entry
+    /// per channel for lists given per channel.
+    pub fn lists(&self) -> Vec<ListEntry<'_>> {
+        KINDS
+            .iter()
+            .filter_map(|kind| Some((kind, self.lists.get(kind.list)?)))
+            .flat_map(|(kind, numbers)| {
+                numbers
+                    .entries()
+                    .into_iter()
+                    .map(move |(channel, numbers)| ListEntry {
+                        list: kind.list,
+                        kind,
+                        channel,
+                        numbers,
+                    })
+            })
+            .collect()
+    }
+    /// The channel an entry listens on, 1-based in the preset and 0-based
+    /// here: its own, or its list's, or `default`.
+    pub fn channel(&self, entry: &ListEntry, default: Option<u8>) -> Option<u4> {
+        entry
+            .channel
+            .or_else(|| self.list_channel(entry.list, default))
+            .and_then(zero_based)
+    }
+    /// The channel (1-16) of every component in `list`, unless the list is
+    /// given per channel.
+    fn list_channel(&self, list: &str, default: Option<u8>) -> Option<u8> {
+        self.channels.get(list).copied().or(default)
+    }
+    pub fn high_resolution(&self) -> Result<CcSet, PresetError> {
+        match &self.high_resolution {
+            Some(ccs) => ccs.parse().map_err(|error| PresetError::List {
+                list: "high_resolution",
+                error,
+            }),
+            None => Ok(CcSet::default()),
+        }
+    }
+    /// The 0-based channels of the pitch-bend faders. Channels outside 1-16
+    /// are skipped, [`Preset::validate`] reports them.
+    pub fn pitch_bend(&self) -> Result<Vec<u4>, PresetError> {
+        let Some(channels) = &self.pitchbend else {
+            return Ok(Vec::new());
+        };
+        let set: CcSet = channels.parse().map_err(|error| PresetError::List {
+            list: "pitchbend",
+            error,
+        })?;
+        Ok(set
+            .iter()
+            .filter_map(|c| c.as_int().checked_sub(1).and_then(u4::try_from))
+            .collect())
+    }
+    /// The encoder mode of every dial that isn't absolute.
+    pub fn encoders(&self) -> Result<Settings<EncoderMode>, PresetError> {
+        let mut encoders = HashMap::new();
+        for (mode, ccs) in &self.encoders {
+            let keys = ccs.settings_keys("encoders")?;
+            encoders.extend(keys.into_iter().map(|key| (key, *mode)));
+        }
+        for (mode, numbers) in self.nrpn.iter().flat_map(|nrpn| &nrpn.encoders) {
+            let keys = nrpn_settings_keys(numbers)?;
+            encoders.extend(keys.into_iter().map(|key| (key, *mode)));
+        }
+        Ok(encoders)
+    }
+    /// The takeover mode of every slider or dial that doesn't jump.
+    pub fn takeover(&self) -> Result<Settings<TakeoverMode>, PresetError> {
+        let mut modes = HashMap::new();
+        for (mode, ccs) in &self.takeover {
+            let keys = ccs.settings_keys("takeover")?;
+            modes.extend(keys.into_iter().map(|key| (key, *mode)));
+        }
+        for (mode, numbers) in self.nrpn.iter().flat_map(|nrpn| &nrpn.takeover) {
+            let keys = nrpn_settings_keys(numbers)?;
+            modes.extend(keys.into_iter().map(|key| (key, *mode)));
+        }
+        Ok(modes)
+    }
+    /// The acceleration curve of every encoder that has one.
+    pub fn accelerations(&self) -> Result<Settings<Acceleration>, PresetError> {
+        let mut accelerations = HashMap::new();
+        for (ccs, entry) in &self.acceleration {
+            let channel = match entry.channel.map(zero_based) {
+                Some(None) => continue,
+                Some(channel) => channel,
+                None => None,
+            };
+            let set: CcSet = ccs.parse().map_err(|error| PresetError::List {
+                list: "acceleration",
+                error,
+            })?;
+            accelerations.extend(
+                set.iter()
+                    .map(|cc| ((channel, Address::Cc(cc)), entry.curve)),
+            );
+        }
+        for (numbers, curve) in self.nrpn.iter().flat_map(|nrpn| &nrpn.acceleration) {
+            let keys = nrpn_settings_keys(numbers)?;
+            accelerations.extend(keys.into_iter().map(|key| (key, *curve)));
+        }
+        Ok(accelerations)
+    }
+    /// Parses every component list, stopping at the first invalid one.
+    pub fn parse(&self, default_channel: Option<u8>) -> Result<Vec<ParsedList>, PresetError> {
+        self.lists()
+            .into_iter()
+            .map(|entry| {
+                entry
+                    .numbers
+                    .parse::<CcSet>()
+                    .map(|set| (entry.kind, self.channel(&entry, default_channel), set))
+                    .map_err(|error| PresetError::List {
+                        list: entry.list,
+                        error,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::num::u14;
+
+    use super::*;
+    use crate::midi::components::Value;
+
+    fn preset(source: &str) -> Preset {
+        serde_yaml::from_str(source).unwrap()
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        preset(source)
+            .validate(source)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lists_can_be_given_per_channel() {
+        let source = r#"
+components:
+  sliders: { 1: "3-11", 2: "3-11" }
+  buttons: "23-31"
+  dials: { 2: "12-21" }
+"#;
+        assert_eq!(messages(source), Vec::<String>::new());
+        let layout = preset(source).layout().unwrap();
+        let sliders: Vec<_> = layout
+            .iter()
+            .filter(|c| c.address() == Address::Cc(u7::from(3)))
+            .map(|c| c.channel().map(|c| c.as_int()))
+            .collect();
+        assert_eq!(sliders, [Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn numbers_clash_on_the_same_channel() {
+        let source = r#"
+components:
+  sliders: { 1: "3-11", 2: "3-11" }
+  buttons: "23-31"
+  dials: { 2: "11-21" }
+"#;
+        assert_eq!(
+            messages(source),
+            ["5: dials: CC 11 is already assigned to sliders"]
+        );
+    }
+
+    #[test]
+    fn per_channel_lists_need_valid_channels() {
+        let source = r#"
+components:
+  sliders: { 0: "3-11", 17: "3-11" }
+  buttons: "23-31"
+  dials: "12-21"
+  channels:
+    sliders: 2
+"#;
+        assert_eq!(
+            messages(source),
+            [
+                "3: sliders: channel 0 is not between 1 and 16",
+                "3: sliders: channel 17 is not between 1 and 16",
+                "6: channels: sliders is already given per channel",
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_apply_per_channel() {
+        let source = r#"
+components:
+  sliders: "3-11"
+  buttons: "23-31"
+  dials: { 1: "12-21", 2: "12-21" }
+  encoders:
+    binary_offset: { 2: "12" }
+  takeover:
+    pickup: { 1: "12" }
+  acceleration:
+    "12": { channel: 2, max: 4 }
+"#;
+        assert_eq!(messages(source), Vec::<String>::new());
+        let layout = preset(source).layout().unwrap();
+        let step = Value::U7(u7::from(65));
+        let decoded: Vec<_> = layout
+            .iter()
+            .filter(|c| c.address() == Address::Cc(u7::from(12)))
+            .map(|c| c.decode(step))
+            .collect();
+        assert_eq!(decoded, [step, Value::Relative(1)]);
+    }
+
+    #[test]
+    fn nrpn_dials_can_be_encoders() {
+        let source = r#"
+components:
+  sliders: "7-11"
+  buttons: "23-31"
+  dials: "12-21"
+  nrpn:
+    dials: "1000-1001"
+    encoders:
+      binary_offset: "1000"
+    acceleration:
+      "1000": { max: 4 }
+"#;
+        assert_eq!(messages(source), Vec::<String>::new());
+        let layout = preset(source).layout().unwrap();
+        let step = Value::U14(u14::from(8193));
+        let decoded: Vec<_> = layout
+            .iter()
+            .filter(|c| matches!(c.address(), Address::Nrpn(_)))
+            .map(|c| c.decode(step))
+            .collect();
+        assert_eq!(decoded, [Value::Relative(1), step]);
+    }
+
+    #[test]
+    fn kinds_and_settings_are_checked() {
+        let source = r#"
+components:
+  sliders: "7-11"
+  buttons: "23-31"
+  dials: { 2: "12-21" }
+  knobs: "40-41"
+  encoders:
+    binary_offset: { 1: "12" }
+  nrpn:
+    sliders: "1000"
+    encoders:
+      twos_complement: "1000"
+"#;
+        assert_eq!(
+            messages(source),
+            [
+                "6: components: 'knobs' is not a kind of component, expected sliders, buttons, dials or pads",
+                "7: encoders: CC 12 on channel 1 is not in dials",
+                "9: nrpn: encoders: NRPN 1000 is not in dials",
+            ]
+        );
+    }
+
+    #[test]
+    fn modifiers_are_buttons_or_pads() {
+        let source = r#"
+components:
+  sliders: "3-11"
+  buttons: "23-31"
+modifiers:
+  - { cc: 23 }
+  - { cc: 3 }
+  - { note: 23 }
+  - { pitchbend: 1 }
+"#;
+        assert_eq!(
+            messages(source),
+            [
+                "5: modifiers: layer 2: CC 3 is not in buttons",
+                "5: modifiers: layer 3: note 23 is not in pads",
+                "5: modifiers: layer 4: pitch bend 1 can't be a modifier, only buttons or pads",
+            ]
+        );
+    }
 }
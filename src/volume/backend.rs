@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+
+use crate::volume::session::Session;
+
+/// What a binding points at: the default output device or a single application.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Master,
+    Process(String),
+    Pid(u32),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Master => write!(f, "master"),
+            Target::Process(name) => write!(f, "process '{}'", name),
+            Target::Pid(pid) => write!(f, "pid {}", pid),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioError {
+    NotFound(Target),
+    Backend(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::NotFound(target) => write!(f, "no audio session found for {}", target),
+            AudioError::Backend(message) => write!(f, "audio backend error: {}", message),
+        }
+    }
+}
+
+impl Error for AudioError {}
+
+/// A backend shared between every bound callback.
+pub type SharedBackend = Arc<Mutex<Box<dyn AudioBackend>>>;
+
+/// A source of audio devices and sessions.
+///
+/// Implementors only need to enumerate; resolving a [`Target`] and reading or
+/// writing its volume and mute go through the returned [`Session`]s by default.
+pub trait AudioBackend: Send {
+    /// Output devices, each exposed as a session controlling its master volume.
+    fn devices(&self) -> Result<Vec<Box<dyn Session>>, AudioError>;
+    /// Per-application sessions.
+    fn sessions(&self) -> Result<Vec<Box<dyn Session>>, AudioError>;
+
+    /// Every session `target` names. An application may have several, such
+    /// as a browser with one stream per tab, and changes apply to all of them.
+    fn resolve_all(&self, target: &Target) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        let found: Vec<_> = match target {
+            Target::Master => self.devices()?.into_iter().take(1).collect(),
+            Target::Process(name) => self
+                .sessions()?
+                .into_iter()
+                .filter(|s| unsafe { s.get_name() } == *name)
+                .collect(),
+            Target::Pid(pid) => self
+                .sessions()?
+                .into_iter()
+                .filter(|s| unsafe { s.get_pid() } == *pid)
+                .collect(),
+        };
+        match found.is_empty() {
+            true => Err(AudioError::NotFound(target.clone())),
+            false => Ok(found),
+        }
+    }
+    /// The first session `target` names, which its volume and mute are read
+    /// from.
+    fn resolve(&self, target: &Target) -> Result<Box<dyn Session>, AudioError> {
+        let mut sessions = self.resolve_all(target)?;
+        Ok(sessions.swap_remove(0))
+    }
+    fn get_volume(&self, target: &Target) -> Result<f32, AudioError> {
+        let session = self.resolve(target)?;
+        Ok(unsafe { session.get_volume() })
+    }
+    fn set_volume(&self, target: &Target, vol: f32) -> Result<(), AudioError> {
+        for session in self.resolve_all(target)? {
+            unsafe { session.set_volume(vol) };
+        }
+        Ok(())
+    }
+    fn get_mute(&self, target: &Target) -> Result<bool, AudioError> {
+        let session = self.resolve(target)?;
+        Ok(unsafe { session.get_mute() })
+    }
+    fn set_mute(&self, target: &Target, mute: bool) -> Result<(), AudioError> {
+        for session in self.resolve_all(target)? {
+            unsafe { session.set_mute(mute) };
+        }
+        Ok(())
+    }
+}
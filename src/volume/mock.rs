@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::volume::backend::{AudioBackend, AudioError, Target};
+use crate::volume::session::Session;
+
+#[derive(Debug)]
+struct MockState {
+    volume: f32,
+    mute: bool,
+}
+
+/// An in-memory session. Clones share their volume and mute, so a change made
+/// through a resolved session is visible to the backend that produced it.
+#[derive(Clone)]
+pub struct MockSession {
+    name: String,
+    pid: u32,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockSession {
+    pub fn new(name: String, pid: u32, volume: f32, mute: bool) -> Self {
+        Self {
+            name,
+            pid,
+            state: Arc::new(Mutex::new(MockState { volume, mute })),
+        }
+    }
+}
+
+impl Session for MockSession {
+    unsafe fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    unsafe fn get_pid(&self) -> u32 {
+        self.pid
+    }
+    unsafe fn get_volume(&self) -> f32 {
+        self.state.lock().unwrap().volume
+    }
+    unsafe fn set_volume(&self, vol: f32) {
+        self.state.lock().unwrap().volume = vol.clamp(0.0, 1.0);
+    }
+    unsafe fn get_mute(&self) -> bool {
+        self.state.lock().unwrap().mute
+    }
+    unsafe fn set_mute(&self, mute: bool) {
+        self.state.lock().unwrap().mute = mute;
+    }
+    fn clone_dyn(&self) -> Box<dyn Session> {
+        Box::new(self.clone())
+    }
+}
+
+/// A backend whose devices, sessions and failures are scripted in memory.
+#[derive(Default)]
+pub struct MockAudioBackend {
+    devices: Vec<MockSession>,
+    sessions: Vec<MockSession>,
+    failures: HashMap<Target, AudioError>,
+}
+
+impl MockAudioBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_device(mut self, name: &str, volume: f32, mute: bool) -> Self {
+        self.devices
+            .push(MockSession::new(name.to_string(), 0, volume, mute));
+        self
+    }
+    pub fn with_session(mut self, name: &str, pid: u32, volume: f32, mute: bool) -> Self {
+        self.sessions
+            .push(MockSession::new(name.to_string(), pid, volume, mute));
+        self
+    }
+    /// Makes every operation on `target` fail with `error` until cleared.
+    pub fn fail(&mut self, target: Target, error: AudioError) {
+        self.failures.insert(target, error);
+    }
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+}
+
+impl AudioBackend for MockAudioBackend {
+    fn devices(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        Ok(self.devices.iter().map(|d| d.clone_dyn()).collect())
+    }
+    fn sessions(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        Ok(self.sessions.iter().map(|s| s.clone_dyn()).collect())
+    }
+    fn resolve_all(&self, target: &Target) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        if let Some(error) = self.failures.get(target) {
+            return Err(error.clone());
+        }
+        let found: Vec<_> = match target {
+            Target::Master => self.devices.iter().take(1).collect(),
+            Target::Process(name) => self.sessions.iter().filter(|s| s.name == *name).collect(),
+            Target::Pid(pid) => self.sessions.iter().filter(|s| s.pid == *pid).collect(),
+        };
+        match found.is_empty() {
+            true => Err(AudioError::NotFound(target.clone())),
+            false => Ok(found.into_iter().map(|s| s.clone_dyn()).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_apply_to_every_session_of_a_target() {
+        let backend = MockAudioBackend::new()
+            .with_session("firefox", 10, 1.0, false)
+            .with_session("firefox", 10, 1.0, false)
+            .with_session("spotify", 20, 1.0, false);
+        let target = Target::Process("firefox".to_string());
+        backend.set_volume(&target, 0.5).unwrap();
+        backend.set_mute(&target, true).unwrap();
+        let sessions = backend.sessions().unwrap();
+        let states: Vec<_> = sessions
+            .iter()
+            .map(|s| unsafe { (s.get_volume(), s.get_mute()) })
+            .collect();
+        assert_eq!(states, [(0.5, true), (0.5, true), (1.0, false)]);
+    }
+
+    #[test]
+    fn unknown_and_failing_targets_are_errors() {
+        let mut backend = MockAudioBackend::new().with_device("master", 1.0, false);
+        let target = Target::Pid(42);
+        assert_eq!(
+            backend.set_volume(&target, 0.5),
+            Err(AudioError::NotFound(target.clone()))
+        );
+        let error = AudioError::Backend("device lost".to_string());
+        backend.fail(Target::Master, error.clone());
+        assert_eq!(backend.get_volume(&Target::Master), Err(error));
+        backend.clear_failures();
+        assert_eq!(backend.get_volume(&Target::Master), Ok(1.0));
+    }
+}
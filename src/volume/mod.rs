@@ -1,5 +1,19 @@
+mod backend;
+mod mock;
+#[cfg(all(target_os = "linux", feature = "pulse-backend"))]
+mod pulseaudio;
+#[cfg(all(target_os = "linux", feature = "pulse-backend"))]
+mod pulsesession;
 mod session;
+#[cfg(all(windows, feature = "windows-backend"))]
 mod winaudio;
+#[cfg(all(windows, feature = "windows-backend"))]
+mod winsession;
 
+pub use backend::{AudioBackend, AudioError, SharedBackend, Target};
+pub use mock::MockAudioBackend;
+#[cfg(all(target_os = "linux", feature = "pulse-backend"))]
+pub use pulseaudio::PulseBackend;
 pub use session::Session;
+#[cfg(all(windows, feature = "windows-backend"))]
 pub use winaudio::{AudioController, CoInitMode};
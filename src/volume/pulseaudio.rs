@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::volume::backend::{AudioBackend, AudioError};
+use crate::volume::pulsesession::{PulseKind, PulseSession};
+use crate::volume::session::Session;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PactlVolume {
+    pub value: u32,
+}
+
+/// One entry of `pactl --format=json list sinks|sink-inputs`, only the fields we use.
+#[derive(Debug, Deserialize)]
+pub(crate) struct PactlEntry {
+    pub index: u32,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub mute: bool,
+    pub volume: HashMap<String, PactlVolume>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+pub(crate) fn pactl(args: &[&str]) -> Result<String, AudioError> {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .map_err(|err| AudioError::Backend(format!("couldn't run pactl: {err}")))?;
+    if !output.status.success() {
+        return Err(AudioError::Backend(format!(
+            "pactl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub(crate) fn list(kind: PulseKind) -> Result<Vec<PactlEntry>, AudioError> {
+    let json = pactl(&["--format=json", "list", kind.list_name()])?;
+    serde_json::from_str(&json)
+        .map_err(|err| AudioError::Backend(format!("couldn't parse pactl output: {err}")))
+}
+
+/// Sinks become devices and sink-inputs become application sessions, one per
+/// stream and told apart by their index, so an application playing several
+/// streams has a session for each. Everything is enumerated on demand, since
+/// streams come and go while midlman runs.
+#[derive(Default)]
+pub struct PulseBackend;
+
+impl PulseBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl AudioBackend for PulseBackend {
+    fn devices(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        let default_sink = pactl(&["get-default-sink"])
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        let mut sinks = list(PulseKind::Sink)?
+            .into_iter()
+            .filter_map(|entry| {
+                let name = entry.name?;
+                Some(PulseSession::new(PulseKind::Sink, entry.index, name, 0))
+            })
+            .collect::<Vec<_>>();
+        // The default sink is the one `Target::Master` resolves to.
+        sinks.sort_by_key(|s| s.name() != default_sink);
+        Ok(sinks
+            .into_iter()
+            .map(|s| Box::new(s) as Box<dyn Session>)
+            .collect())
+    }
+
+    fn sessions(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        Ok(list(PulseKind::SinkInput)?
+            .into_iter()
+            .filter_map(|entry| {
+                let name = entry.properties.get("application.process.binary")?.clone();
+                let pid = entry
+                    .properties
+                    .get("application.process.id")
+                    .and_then(|pid| pid.parse::<u32>().ok())
+                    .unwrap_or(0);
+                Some(PulseSession::new(
+                    PulseKind::SinkInput,
+                    entry.index,
+                    name,
+                    pid,
+                ))
+            })
+            .filter(|s| !s.name().is_empty())
+            .map(|s| Box::new(s) as Box<dyn Session>)
+            .collect())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::volume::pulseaudio::{list, pactl, PactlEntry};
+use crate::volume::session::Session;
+
+/// `pa_volume_t` value for 100%.
+const PA_VOLUME_NORM: f32 = 65536.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PulseKind {
+    Sink,
+    SinkInput,
+}
+
+impl PulseKind {
+    pub(crate) fn list_name(self) -> &'static str {
+        match self {
+            PulseKind::Sink => "sinks",
+            PulseKind::SinkInput => "sink-inputs",
+        }
+    }
+    fn command_name(self) -> &'static str {
+        match self {
+            PulseKind::Sink => "sink",
+            PulseKind::SinkInput => "sink-input",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PulseSession {
+    kind: PulseKind,
+    index: u32,
+    name: String,
+    pid: u32,
+}
+
+impl PulseSession {
+    pub fn new(kind: PulseKind, index: u32, name: String, pid: u32) -> Self {
+        Self {
+            kind,
+            index,
+            name,
+            pid,
+        }
+    }
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+    fn entry(&self) -> Option<PactlEntry> {
+        list(self.kind)
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't list {}: {err}", self.kind.list_name());
+                Vec::new()
+            })
+            .into_iter()
+            .find(|e| e.index == self.index)
+    }
+}
+
+impl Session for PulseSession {
+    unsafe fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    unsafe fn get_pid(&self) -> u32 {
+        self.pid
+    }
+
+    unsafe fn get_volume(&self) -> f32 {
+        match self.entry() {
+            Some(entry) if !entry.volume.is_empty() => {
+                let total: u32 = entry.volume.values().map(|v| v.value).sum();
+                total as f32 / entry.volume.len() as f32 / PA_VOLUME_NORM
+            }
+            _ => {
+                eprintln!("ERROR: Couldn't get volume of {}", self.name);
+                0.0
+            }
+        }
+    }
+    // PulseAudio volumes are already on a perceptual (cubic) scale, so unlike
+    // the Windows sessions no extra curve is applied here.
+    unsafe fn set_volume(&self, vol: f32) {
+        let raw = (vol.clamp(0.0, 1.0) * PA_VOLUME_NORM).round() as u32;
+        let command = format!("set-{}-volume", self.kind.command_name());
+        pactl(&[&command, &self.index.to_string(), &raw.to_string()]).unwrap_or_else(|err| {
+            eprintln!("ERROR: Couldn't set volume: {err}");
+            String::new()
+        });
+    }
+    unsafe fn set_mute(&self, mute: bool) {
+        let command = format!("set-{}-mute", self.kind.command_name());
+        let mute = if mute { "1" } else { "0" };
+        pactl(&[&command, &self.index.to_string(), mute]).unwrap_or_else(|err| {
+            eprintln!("ERROR: Couldn't set mute: {err}");
+            String::new()
+        });
+    }
+    unsafe fn get_mute(&self) -> bool {
+        self.entry().map(|e| e.mute).unwrap_or_else(|| {
+            eprintln!("ERROR: Couldn't get mute of {}", self.name);
+            false
+        })
+    }
+
+    fn clone_dyn(&self) -> Box<dyn Session> {
+        Box::new(self.clone())
+    }
+}
@@ -1,6 +1,5 @@
 use std::collections::HashSet;
 use std::process::exit;
-use std::sync::{Arc, Mutex};
 use windows::core::ComInterface;
 use windows::Win32::{
     Media::Audio::{
@@ -18,7 +17,9 @@ use windows::Win32::{
     },
 };
 
-use crate::volume::session::{ApplicationSession, EndPointSession, Session};
+use crate::volume::backend::{AudioBackend, AudioError};
+use crate::volume::session::Session;
+use crate::volume::winsession::{ApplicationSession, EndPointSession};
 
 pub struct AudioController {
     pub sessions: Vec<Box<dyn Session>>,
@@ -180,12 +181,24 @@ impl AudioController {
 
         sessions
     }
+}
 
-    pub unsafe fn get_session_by_name(&self, name: String) -> Option<Arc<Mutex<Box<dyn Session>>>> {
-        let session = self.sessions.iter().find(|i| i.get_name() == name);
-        match session {
-            Some(data) => Some(Arc::new(Mutex::new(data.clone()))),
-            None => None,
-        }
+// Devices are enumerated as `EndPointSession`s with pid 0, applications never are.
+impl AudioBackend for AudioController {
+    fn devices(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        Ok(self
+            .sessions
+            .iter()
+            .filter(|s| unsafe { s.get_pid() } == 0)
+            .cloned()
+            .collect())
+    }
+    fn sessions(&self) -> Result<Vec<Box<dyn Session>>, AudioError> {
+        Ok(self
+            .sessions
+            .iter()
+            .filter(|s| unsafe { s.get_pid() } != 0)
+            .cloned()
+            .collect())
     }
 }
@@ -0,0 +1,188 @@
+use std::f32::consts::LN_2;
+use std::process::exit;
+
+use windows::{
+    core::GUID,
+    Win32::{
+        Foundation::BOOL,
+        Media::Audio::{Endpoints::IAudioEndpointVolume, ISimpleAudioVolume},
+    },
+};
+
+use crate::volume::session::Session;
+
+fn linear_to_logarithmic(vol: f32) -> f32 {
+    let vol = vol.clamp(0.0, 1.0);
+    // 2^(vol^4) - 1, precise near 0
+    (vol.powf(4.0) * LN_2).exp_m1()
+}
+
+/// The inverse of [`linear_to_logarithmic`], so volumes read back are on the
+/// same scale as the ones set.
+fn logarithmic_to_linear(vol: f32) -> f32 {
+    let vol = vol.clamp(0.0, 1.0);
+    (vol.ln_1p() / LN_2).powf(0.25)
+}
+
+#[derive(Clone)]
+pub struct EndPointSession {
+    simple_audio_volume: IAudioEndpointVolume,
+    name: String,
+    pid: u32,
+    guid: GUID,
+}
+
+impl EndPointSession {
+    pub fn new(simple_audio_volume: IAudioEndpointVolume, name: String, pid: u32) -> Self {
+        let guid = GUID::new().unwrap_or_else(|err| {
+            eprintln!("ERROR: Couldn't generate GUID {err}");
+            exit(1);
+        });
+
+        Self {
+            simple_audio_volume,
+            name,
+            pid,
+            guid,
+        }
+    }
+}
+
+unsafe impl Send for EndPointSession {}
+
+impl Session for EndPointSession {
+    unsafe fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    unsafe fn get_pid(&self) -> u32 {
+        self.pid
+    }
+
+    unsafe fn get_volume(&self) -> f32 {
+        let vol = self
+            .simple_audio_volume
+            .GetMasterVolumeLevelScalar()
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't get volume {err}");
+                0.0
+            });
+        logarithmic_to_linear(vol)
+    }
+    unsafe fn set_volume(&self, vol: f32) {
+        let vol = linear_to_logarithmic(vol);
+        self.simple_audio_volume
+            .SetMasterVolumeLevelScalar(vol, &self.guid)
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't set volume: {err}");
+            });
+    }
+    unsafe fn set_mute(&self, mute: bool) {
+        self.simple_audio_volume
+            .SetMute(mute, &self.guid)
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't set mute: {err}");
+            });
+    }
+    unsafe fn get_mute(&self) -> bool {
+        self.simple_audio_volume
+            .GetMute()
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't get mute {err}");
+                BOOL(0)
+            })
+            .as_bool()
+    }
+
+    fn clone_dyn(&self) -> Box<dyn Session> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Clone)]
+pub struct ApplicationSession {
+    simple_audio_volume: ISimpleAudioVolume,
+    name: String,
+    pid: u32,
+    guid: GUID,
+}
+
+impl ApplicationSession {
+    pub fn new(simple_audio_volume: ISimpleAudioVolume, name: String, pid: u32) -> Self {
+        let guid = GUID::new().unwrap_or_else(|err| {
+            eprintln!("ERROR: Couldn't generate GUID {err}");
+            exit(1);
+        });
+
+        Self {
+            simple_audio_volume,
+            name,
+            pid,
+            guid,
+        }
+    }
+}
+
+unsafe impl Send for ApplicationSession {}
+
+impl Session for ApplicationSession {
+    unsafe fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    unsafe fn get_pid(&self) -> u32 {
+        self.pid
+    }
+
+    unsafe fn get_volume(&self) -> f32 {
+        let vol = self
+            .simple_audio_volume
+            .GetMasterVolume()
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't get volume {err}");
+                0.0
+            });
+        logarithmic_to_linear(vol)
+    }
+    unsafe fn set_volume(&self, vol: f32) {
+        let vol = linear_to_logarithmic(vol);
+        self.simple_audio_volume
+            .SetMasterVolume(vol, &self.guid)
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't set volume: {err}");
+            });
+    }
+    unsafe fn set_mute(&self, mute: bool) {
+        self.simple_audio_volume
+            .SetMute(mute, &self.guid)
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't set mute: {err}");
+            });
+    }
+    unsafe fn get_mute(&self) -> bool {
+        self.simple_audio_volume
+            .GetMute()
+            .unwrap_or_else(|err| {
+                eprintln!("ERROR: Couldn't get mute {err}");
+                BOOL(0)
+            })
+            .as_bool()
+    }
+    fn clone_dyn(&self) -> Box<dyn Session> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volumes_read_back_as_set() {
+        for step in 0..=100 {
+            let vol = step as f32 / 100.0;
+            let read = logarithmic_to_linear(linear_to_logarithmic(vol));
+            assert!((read - vol).abs() < 1e-3, "{vol} read back as {read}");
+        }
+    }
+}
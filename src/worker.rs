@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::iter;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::midi::FeedbackCallback;
+use crate::volume::{AudioBackend, AudioError, SharedBackend, Target};
+
+/// The last volume the worker applied or read for each target, for soft
+/// takeover to read without waiting on the backend.
+pub type Volumes = Arc<Mutex<HashMap<Target, f32>>>;
+
+/// What a binding asks of a target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// Sets the volume, `0.0..=1.0`.
+    SetVolume(f32),
+    /// Moves the volume by an amount, negative to lower it, for endless
+    /// encoders.
+    StepVolume(f32),
+    SetMute(bool),
+    ToggleMute,
+    /// Reads the target's volume, and shows its state if there is feedback.
+    Refresh,
+}
+
+/// A change queued by the MIDI thread for the [`AudioWorker`].
+#[derive(Clone)]
+pub struct AudioEvent {
+    pub target: Target,
+    pub change: Change,
+    /// Shows the target's state on the controller once the change is applied.
+    pub feedback: Option<Box<dyn FeedbackCallback>>,
+}
+
+impl AudioEvent {
+    pub fn new(target: Target, change: Change) -> Self {
+        Self {
+            target,
+            change,
+            feedback: None,
+        }
+    }
+    pub fn with_feedback(mut self, feedback: Option<Box<dyn FeedbackCallback>>) -> Self {
+        self.feedback = feedback;
+        self
+    }
+    /// The single event with the effect of `self` followed by `next`, if
+    /// there is one. Both are for the same target.
+    fn merge(&self, next: &AudioEvent) -> Option<AudioEvent> {
+        let change = match (self.change, next.change) {
+            (Change::SetVolume(_) | Change::StepVolume(_), Change::SetVolume(volume)) => {
+                Change::SetVolume(volume)
+            }
+            (Change::SetVolume(volume), Change::StepVolume(amount)) => {
+                Change::SetVolume((volume + amount).clamp(0.0, 1.0))
+            }
+            (Change::StepVolume(a), Change::StepVolume(b)) => Change::StepVolume(a + b),
+            // a read for takeover is redundant next to anything else
+            (Change::Refresh, change) if self.feedback.is_none() => change,
+            (change, Change::Refresh) if next.feedback.is_none() => change,
+            _ => return None,
+        };
+        Some(AudioEvent {
+            target: next.target.clone(),
+            change,
+            feedback: next.feedback.clone().or_else(|| self.feedback.clone()),
+        })
+    }
+}
+
+/// Applies [`AudioEvent`]s to the backend on its own thread, so slow backend
+/// calls never hold up MIDI input. Events that queue up while a call is in
+/// progress are coalesced, so only the latest volume of each target is set.
+/// With `debug`, every change is printed as it is made.
+pub struct AudioWorker {
+    events: Sender<AudioEvent>,
+    volumes: Volumes,
+}
+
+impl AudioWorker {
+    pub fn spawn(backend: SharedBackend, debug: bool) -> Self {
+        let (events, receiver) = mpsc::channel();
+        let volumes = Volumes::default();
+        let worker_volumes = volumes.clone();
+        thread::spawn(move || Self::run(backend, worker_volumes, receiver, debug));
+        Self { events, volumes }
+    }
+    /// Where bound callbacks send their events.
+    pub fn events(&self) -> Sender<AudioEvent> {
+        self.events.clone()
+    }
+    pub fn volumes(&self) -> Volumes {
+        self.volumes.clone()
+    }
+    /// Applies events until every sender is gone.
+    fn run(backend: SharedBackend, volumes: Volumes, receiver: Receiver<AudioEvent>, debug: bool) {
+        while let Ok(event) = receiver.recv() {
+            let pending = iter::once(event).chain(receiver.try_iter()).collect();
+            for event in Self::coalesce(pending) {
+                Self::apply(&backend, &volumes, event, debug);
+            }
+        }
+    }
+    /// Merges each event into the last one queued for its target, where the
+    /// two have the effect of one. Order is kept between events for the same
+    /// target.
+    fn coalesce(events: Vec<AudioEvent>) -> Vec<AudioEvent> {
+        let mut coalesced: Vec<AudioEvent> = Vec::with_capacity(events.len());
+        for event in events {
+            let last = coalesced
+                .iter_mut()
+                .rev()
+                .find(|queued| queued.target == event.target);
+            match last.and_then(|last| Some((last.merge(&event)?, last))) {
+                Some((merged, last)) => *last = merged,
+                None => coalesced.push(event),
+            }
+        }
+        coalesced
+    }
+    fn apply(backend: &SharedBackend, volumes: &Volumes, event: AudioEvent, debug: bool) {
+        let target = &event.target;
+        let result = {
+            let backend = backend.lock().unwrap();
+            Self::change(&**backend, target, event.change, debug)
+        };
+        match result {
+            Ok(volume) => {
+                if let Some(volume) = volume {
+                    volumes.lock().unwrap().insert(target.clone(), volume);
+                }
+                event.feedback.iter().for_each(|feedback| feedback());
+            }
+            // feedback shows a target that isn't running as off
+            Err(AudioError::NotFound(_)) if event.change == Change::Refresh => {
+                event.feedback.iter().for_each(|feedback| feedback());
+            }
+            Err(err) => eprintln!("ERROR: {err}"),
+        }
+    }
+    /// Makes `change` to `target`, returning its volume if that is now known.
+    fn change(
+        backend: &dyn AudioBackend,
+        target: &Target,
+        change: Change,
+        debug: bool,
+    ) -> Result<Option<f32>, AudioError> {
+        let volume = match change {
+            Change::SetVolume(volume) => volume,
+            Change::StepVolume(amount) => (backend.get_volume(target)? + amount).clamp(0.0, 1.0),
+            Change::SetMute(mute) => {
+                if debug {
+                    println!("Setting {} mute to {}", target, mute);
+                }
+                return backend.set_mute(target, mute).map(|()| None);
+            }
+            Change::ToggleMute => {
+                let mute = !backend.get_mute(target)?;
+                if debug {
+                    println!("Setting {} mute to {}", target, mute);
+                }
+                return backend.set_mute(target, mute).map(|()| None);
+            }
+            Change::Refresh => return backend.get_volume(target).map(Some),
+        };
+        if debug {
+            println!("Setting {} volume to {}", target, volume);
+        }
+        backend.set_volume(target, volume).map(|()| Some(volume))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn event(name: &str, change: Change) -> AudioEvent {
+        AudioEvent::new(Target::Process(name.to_string()), change)
+    }
+
+    fn changes(events: Vec<AudioEvent>) -> Vec<(Target, Change)> {
+        AudioWorker::coalesce(events)
+            .into_iter()
+            .map(|event| (event.target, event.change))
+            .collect()
+    }
+
+    fn spotify(change: Change) -> (Target, Change) {
+        (Target::Process("Spotify".to_string()), change)
+    }
+
+    #[test]
+    fn only_the_last_volume_is_set() {
+        let events = vec![
+            event("Spotify", Change::SetVolume(0.2)),
+            event("Spotify", Change::SetVolume(0.4)),
+            event("Spotify", Change::SetVolume(0.3)),
+        ];
+        assert_eq!(changes(events), [spotify(Change::SetVolume(0.3))]);
+    }
+
+    #[test]
+    fn steps_add_up() {
+        let events = vec![
+            event("Spotify", Change::StepVolume(0.02)),
+            event("Spotify", Change::StepVolume(0.02)),
+            event("Spotify", Change::StepVolume(-0.01)),
+        ];
+        let [(_, Change::StepVolume(amount))] = changes(events)[..] else {
+            panic!("a single step was expected");
+        };
+        assert!((amount - 0.03).abs() < 1e-6);
+        let events = vec![
+            event("Spotify", Change::SetVolume(0.5)),
+            event("Spotify", Change::StepVolume(0.1)),
+        ];
+        assert_eq!(changes(events), [spotify(Change::SetVolume(0.6))]);
+    }
+
+    #[test]
+    fn a_queued_volume_absorbs_a_refresh() {
+        let events = vec![
+            event("Spotify", Change::Refresh),
+            event("Spotify", Change::SetVolume(0.4)),
+            event("Spotify", Change::Refresh),
+        ];
+        assert_eq!(changes(events), [spotify(Change::SetVolume(0.4))]);
+    }
+
+    #[test]
+    fn targets_keep_their_order() {
+        let events = vec![
+            event("Spotify", Change::SetVolume(0.2)),
+            event("Firefox", Change::SetMute(true)),
+            event("Spotify", Change::SetVolume(0.4)),
+            event("Spotify", Change::ToggleMute),
+            event("Firefox", Change::SetMute(false)),
+        ];
+        let firefox = |change| (Target::Process("Firefox".to_string()), change);
+        assert_eq!(
+            changes(events),
+            [
+                spotify(Change::SetVolume(0.4)),
+                firefox(Change::SetMute(true)),
+                spotify(Change::ToggleMute),
+                firefox(Change::SetMute(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn feedback_is_kept_when_merging() {
+        let shown = Arc::new(Mutex::new(0));
+        let counter = shown.clone();
+        let show = move || *counter.lock().unwrap() += 1;
+        let events = vec![
+            event("Spotify", Change::SetVolume(0.2)).with_feedback(Some(Box::new(show))),
+            event("Spotify", Change::SetVolume(0.4)),
+        ];
+        let coalesced = AudioWorker::coalesce(events);
+        assert_eq!(coalesced.len(), 1);
+        assert_eq!(coalesced[0].change, Change::SetVolume(0.4));
+        coalesced[0].feedback.iter().for_each(|feedback| feedback());
+        assert_eq!(*shown.lock().unwrap(), 1);
+    }
+}
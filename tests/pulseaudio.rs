@@ -0,0 +1,108 @@
+//! Runs the PulseAudio backend against a null sink on the local daemon
+//! (`pulseaudio` or `pipewire-pulse`), with `pacat` playing silence into it.
+//! Needs `pactl` and `pacat`, so it is ignored by default:
+//!
+//! ```bash
+//! cargo test --test pulseaudio -- --ignored
+//! ```
+#![cfg(all(target_os = "linux", feature = "pulse-backend"))]
+
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use midlman::volume::{AudioBackend, PulseBackend, Target};
+
+const SINK: &str = "midlman_test";
+
+/// A null sink with two streams from one application playing into it,
+/// removed on drop.
+struct NullSink {
+    module: String,
+    streams: Vec<Child>,
+}
+
+impl NullSink {
+    fn load() -> Self {
+        let output = Command::new("pactl")
+            .args(["load-module", "module-null-sink"])
+            .arg(format!("sink_name={SINK}"))
+            .output()
+            .expect("couldn't run pactl");
+        assert!(output.status.success(), "couldn't load module-null-sink");
+        let module = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let streams = (0..2)
+            .map(|_| {
+                Command::new("pacat")
+                    .arg(format!("--device={SINK}"))
+                    .arg("/dev/zero")
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .expect("couldn't run pacat")
+            })
+            .collect();
+        Self { module, streams }
+    }
+}
+
+impl Drop for NullSink {
+    fn drop(&mut self) {
+        for stream in &mut self.streams {
+            let _ = stream.kill();
+            let _ = stream.wait();
+        }
+        let _ = Command::new("pactl")
+            .args(["unload-module", &self.module])
+            .status();
+    }
+}
+
+fn pacat_volumes(backend: &PulseBackend) -> Vec<f32> {
+    backend
+        .sessions()
+        .unwrap()
+        .iter()
+        .filter(|s| unsafe { s.get_name() } == "pacat")
+        .map(|s| unsafe { s.get_volume() })
+        .collect()
+}
+
+#[test]
+#[ignore = "needs a PulseAudio or PipeWire daemon"]
+fn null_sink() {
+    let _sink = NullSink::load();
+    let backend = PulseBackend::new();
+
+    let device = backend
+        .devices()
+        .unwrap()
+        .into_iter()
+        .find(|d| unsafe { d.get_name() } == SINK)
+        .expect("the null sink is listed as a device");
+    unsafe {
+        device.set_volume(0.5);
+        assert!((device.get_volume() - 0.5).abs() < 0.01);
+        device.set_mute(true);
+        assert!(device.get_mute());
+    }
+
+    // both streams show up, although they come from the same binary
+    let mut volumes = Vec::new();
+    for _ in 0..50 {
+        volumes = pacat_volumes(&backend);
+        if volumes.len() == 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(volumes.len(), 2, "expected a session per pacat stream");
+
+    let target = Target::Process("pacat".to_string());
+    backend.set_volume(&target, 0.25).unwrap();
+    for volume in pacat_volumes(&backend) {
+        assert!((volume - 0.25).abs() < 0.01, "{volume}");
+    }
+    backend.set_mute(&target, true).unwrap();
+    assert!(backend.get_mute(&target).unwrap());
+}